@@ -0,0 +1,195 @@
+//! Long-running agent.
+//!
+//! The agent periodically fetches SSH keys from the metadata provider, and
+//! rewrites the authorized keys fragment of each configured user whenever
+//! the set of keys changes. Progress is reported to the service manager via
+//! `sd_notify`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use libsystemd::daemon::{self, NotifyState};
+use slog_scope::{debug, info, warn};
+
+use crate::config::SshKeysConfig;
+use crate::errors::ErrorKind;
+use crate::{metadata, providers, retry};
+
+/// Default interval between synchronizations.
+pub(crate) const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Default maximum delay between synchronizations, after failures.
+pub(crate) const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// Agent keeping SSH keys in sync with the metadata provider.
+#[derive(Clone, Debug)]
+pub(crate) struct Agent {
+    provider: String,
+    users: Vec<String>,
+    policy: SshKeysConfig,
+    interval: Duration,
+    max_backoff: Duration,
+}
+
+impl Agent {
+    /// Build an agent syncing SSH keys from `provider` for `users`.
+    pub(crate) fn new(provider: &str, users: Vec<String>, policy: SshKeysConfig) -> Self {
+        Self {
+            provider: provider.to_string(),
+            users,
+            policy,
+            interval: DEFAULT_INTERVAL,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Set the interval between synchronizations.
+    pub(crate) fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the maximum delay between synchronizations, after failures.
+    pub(crate) fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Run the agent loop. This only returns on setup errors.
+    pub(crate) fn run(&self) -> Result<()> {
+        // Fail early on unknown users, rather than retrying forever.
+        for username in &self.users {
+            providers::get_user(username).context(ErrorKind::InvalidConfig)?;
+        }
+
+        let watchdog = daemon::watchdog_enabled(false);
+        let mut failures: u32 = 0;
+        let mut ready = false;
+        loop {
+            let status = match self.sync() {
+                Ok(updated) => {
+                    failures = 0;
+                    format!(
+                        "SSH keys in sync for {} user(s), {} updated",
+                        self.users.len(),
+                        updated
+                    )
+                }
+                Err(e) => {
+                    failures = failures.saturating_add(1);
+                    warn!("failed to sync ssh keys: {:#}", e);
+                    format!("failed to sync SSH keys ({failures} consecutive failures): {e:#}")
+                }
+            };
+
+            let mut state = vec![NotifyState::Status(status)];
+            if !ready {
+                state.push(NotifyState::Ready);
+                ready = true;
+            }
+            notify(&state);
+
+            let delay = next_delay(self.interval, self.max_backoff, failures);
+            debug!("next ssh keys sync in {}s", delay.as_secs());
+            sleep(delay, watchdog);
+        }
+    }
+
+    /// Sync SSH keys once for all users, returning the number of updated users.
+    fn sync(&self) -> Result<usize> {
+        // Each round of fetches gets its own deadline, if configured.
+        retry::rearm_deadline();
+        let provider =
+            metadata::fetch_metadata(&self.provider).context("fetching metadata from provider")?;
+        let keys = self
+            .policy
+            .filter_keys(provider.ssh_keys().context("fetching ssh keys")?);
+        let wanted: BTreeSet<String> = keys.iter().map(ToString::to_string).collect();
+
+        let mut updated = 0;
+        for username in &self.users {
+            let user = providers::get_user(username)?;
+            let path = providers::ssh_keys_path(&user);
+            let current = match fs::read_to_string(&path) {
+                Ok(content) => content.lines().map(ToString::to_string).collect(),
+                Err(e) if e.kind() == NotFound => BTreeSet::new(),
+                Err(e) => return Err(e).with_context(|| format!("failed to read {path:?}")),
+            };
+            if current == wanted {
+                debug!("ssh keys for user {} unchanged", username);
+                continue;
+            }
+
+            info!("ssh keys for user {} changed, updating", username);
+            providers::write_ssh_keys(user, keys.clone())
+                .with_context(|| format!("writing ssh keys for user {username}"))?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+}
+
+/// Compute the delay before the next synchronization.
+///
+/// After failures, the interval is doubled for each consecutive failure, up
+/// to `max_backoff`.
+fn next_delay(interval: Duration, max_backoff: Duration, failures: u32) -> Duration {
+    if failures == 0 {
+        return interval;
+    }
+    let factor = 2u32.saturating_pow(failures);
+    interval
+        .checked_mul(factor)
+        .unwrap_or(Duration::MAX)
+        .min(max_backoff.max(interval))
+}
+
+/// Sleep for `delay`, pinging the service manager watchdog if enabled.
+fn sleep(delay: Duration, watchdog: Option<Duration>) {
+    let deadline = Instant::now() + delay;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+        match watchdog {
+            Some(timeout) => {
+                thread::sleep(remaining.min(timeout / 2));
+                notify(&[NotifyState::Watchdog]);
+            }
+            None => thread::sleep(remaining),
+        }
+    }
+}
+
+/// Send a notification to the service manager, if any.
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = daemon::notify(false, state) {
+        warn!("failed to notify service manager: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_delay() {
+        let interval = Duration::from_secs(300);
+        let max_backoff = Duration::from_secs(3600);
+        let delays: Vec<_> = (0..6)
+            .map(|n| next_delay(interval, max_backoff, n).as_secs())
+            .collect();
+        assert_eq!(delays, vec![300, 600, 1200, 2400, 3600, 3600]);
+
+        // never overflows
+        assert_eq!(next_delay(interval, max_backoff, u32::MAX), max_backoff);
+
+        // backoff never shortens the interval
+        assert_eq!(next_delay(interval, Duration::from_secs(60), 3), interval);
+    }
+}
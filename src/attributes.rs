@@ -0,0 +1,448 @@
+//! Rendering of metadata attributes.
+//!
+//! Attributes are written as `AFTERBURN_<KEY>` variables, or with a
+//! configured prefix, sorted by key so that output is stable across runs.
+//! Values may contain arbitrary text (e.g. user-provided tags and labels),
+//! so they are quoted and escaped as needed for the selected format.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// Default prefix of attribute variable names.
+pub(crate) const DEFAULT_PREFIX: &str = "AFTERBURN_";
+
+/// Output format for the attributes file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AttributesFormat {
+    /// systemd `EnvironmentFile=`
+    #[default]
+    Env,
+    /// POSIX shell, for sourcing
+    Shell,
+    /// JSON object, with typed values
+    Json,
+}
+
+/// Type of attribute values in JSON output.
+///
+/// Attributes are strings, unless their provider declares another type next
+/// to its normalized keys. IDs are kept as strings on purpose, even if
+/// numeric, as they may have leading zeros or exceed the precision of JSON
+/// numbers in some parsers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ValueType {
+    /// Number.
+    Number,
+    /// `true` or `false`.
+    Boolean,
+    /// String, or `null` if empty.
+    Nullable,
+}
+
+impl ValueType {
+    /// Convert `value` to JSON, keeping it as a string if it doesn't parse.
+    fn to_json(self, value: String) -> serde_json::Value {
+        let typed = match self {
+            ValueType::Number => value.parse().ok().map(serde_json::Value::Number),
+            ValueType::Boolean => value.parse().ok().map(serde_json::Value::Bool),
+            ValueType::Nullable => value.is_empty().then_some(serde_json::Value::Null),
+        };
+        typed.unwrap_or(serde_json::Value::String(value))
+    }
+}
+
+/// Provider-agnostic attributes, emitted in addition to provider ones.
+///
+/// Each provider declares which of its attributes map to normalized ones,
+/// as a `Normalized<&str>` of attribute keys; values are then picked from
+/// its attributes into a `Normalized<String>`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Normalized<T> {
+    pub instance_id: Option<T>,
+    pub instance_type: Option<T>,
+    pub region: Option<T>,
+    pub zone: Option<T>,
+    pub ipv4_private: Option<T>,
+    pub ipv4_public: Option<T>,
+    pub ipv6: Option<T>,
+}
+
+impl<T> Normalized<T> {
+    /// No normalized attributes.
+    pub(crate) const NONE: Self = Normalized {
+        instance_id: None,
+        instance_type: None,
+        region: None,
+        zone: None,
+        ipv4_private: None,
+        ipv4_public: None,
+        ipv6: None,
+    };
+
+    /// Return fields by normalized attribute key.
+    pub(crate) fn fields(self) -> [(&'static str, Option<T>); 7] {
+        [
+            ("INSTANCE_ID", self.instance_id),
+            ("INSTANCE_TYPE", self.instance_type),
+            ("REGION", self.region),
+            ("ZONE", self.zone),
+            ("IPV4_PRIVATE", self.ipv4_private),
+            ("IPV4_PUBLIC", self.ipv4_public),
+            ("IPV6", self.ipv6),
+        ]
+    }
+}
+
+impl Normalized<&str> {
+    /// Pick normalized values from provider attributes, by key.
+    ///
+    /// Instance types given as resource paths, e.g. GCP
+    /// `projects/<number>/machineTypes/<type>`, are reduced to their last
+    /// component.
+    pub(crate) fn pick(&self, attributes: &HashMap<String, String>) -> Normalized<String> {
+        let get = |key: Option<&str>| key.and_then(|k| attributes.get(k)).cloned();
+        Normalized {
+            instance_id: get(self.instance_id),
+            instance_type: get(self.instance_type).map(|t| match t.rsplit_once('/') {
+                Some((_, name)) => name.to_string(),
+                None => t,
+            }),
+            region: get(self.region),
+            zone: get(self.zone),
+            ipv4_private: get(self.ipv4_private),
+            ipv4_public: get(self.ipv4_public),
+            ipv6: get(self.ipv6),
+        }
+    }
+
+    /// Return mapped keys missing from provider attributes.
+    #[cfg(test)]
+    pub(crate) fn missing_keys(&self, attributes: &HashMap<String, String>) -> Vec<&str> {
+        self.clone()
+            .fields()
+            .into_iter()
+            .filter_map(|(_, key)| key)
+            .filter(|key| !attributes.contains_key(*key))
+            .collect()
+    }
+}
+
+impl Normalized<String> {
+    /// Convert into attributes for `platform`, including the platform name.
+    pub(crate) fn into_attributes(self, platform: &str) -> HashMap<String, String> {
+        let mut out: HashMap<String, String> = self
+            .fields()
+            .into_iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v?)))
+            .collect();
+        out.insert("PLATFORM".into(), platform.into());
+        out
+    }
+}
+
+/// Render attributes in the given format, with names prefixed by `prefix`.
+///
+/// In JSON, values of attributes for which `value_type` returns a type are
+/// converted to it; others are strings.
+pub(crate) fn render(
+    attributes: HashMap<String, String>,
+    format: AttributesFormat,
+    prefix: &str,
+    value_type: fn(&str) -> Option<ValueType>,
+) -> Result<String> {
+    let prefixed = |attributes: HashMap<String, String>| -> BTreeMap<String, String> {
+        attributes
+            .into_iter()
+            .map(|(k, v)| (format!("{prefix}{k}"), v))
+            .collect()
+    };
+    let content = match format {
+        AttributesFormat::Env => render_lines(&prefixed(attributes), env_quote),
+        AttributesFormat::Shell => render_lines(&prefixed(attributes), shell_quote),
+        AttributesFormat::Json => {
+            let attributes: BTreeMap<String, serde_json::Value> = attributes
+                .into_iter()
+                .map(|(k, v)| {
+                    let v = match value_type(&k) {
+                        Some(t) => t.to_json(v),
+                        None => serde_json::Value::String(v),
+                    };
+                    (format!("{prefix}{k}"), v)
+                })
+                .collect();
+            let mut json = serde_json::to_string_pretty(&attributes)
+                .context("failed to serialize attributes")?;
+            json.push('\n');
+            json
+        }
+    };
+    Ok(content)
+}
+
+/// Whether `name` matches a glob `pattern`, where `*` matches any sequence
+/// of characters and `?` any single character.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the name position it was tried at
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` match one more character
+                Some((star, start)) => {
+                    backtrack = Some((star, start + 1));
+                    p = star + 1;
+                    n = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn render_lines(attributes: &BTreeMap<String, String>, quote: fn(&str) -> String) -> String {
+    attributes
+        .iter()
+        .map(|(k, v)| format!("{}={}\n", k, quote(v)))
+        .collect()
+}
+
+/// Whether `value` can be written without quoting in any format.
+fn is_plain(value: &str) -> bool {
+    value.chars().all(|c| {
+        c.is_ascii_alphanumeric()
+            || matches!(c, '_' | '-' | '.' | ',' | ':' | '/' | '@' | '%' | '+')
+    })
+}
+
+/// Quote a value for systemd `EnvironmentFile=`.
+///
+/// Within double quotes, systemd unescapes `\"`, `\\`, `` \` `` and `\$`,
+/// and keeps newlines.
+fn env_quote(value: &str) -> String {
+    if is_plain(value) {
+        return value.to_string();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '`' | '$') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quote a value for a POSIX shell.
+///
+/// Single quotes preserve everything literally, except single quotes
+/// themselves, which are closed, escaped and reopened.
+fn shell_quote(value: &str) -> String {
+    if is_plain(value) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn attributes() -> HashMap<String, String> {
+        maplit::hashmap! {
+            "ZONE".to_string() => "us-east-1a".to_string(),
+            "EMPTY".to_string() => String::new(),
+            "TAGS".to_string() => "env=prod, owner=\"ops\" $HOME `id` \\ it's\nsecond line".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalized() {
+        const KEYS: Normalized<&str> = Normalized {
+            instance_id: Some("CLOUD_INSTANCE_ID"),
+            zone: Some("CLOUD_ZONE"),
+            ipv6: Some("CLOUD_IPV6"),
+            ..Normalized::NONE
+        };
+        let attrs = maplit::hashmap! {
+            "CLOUD_INSTANCE_ID".to_string() => "i-1".to_string(),
+            "CLOUD_ZONE".to_string() => "zone-a".to_string(),
+            "CLOUD_OTHER".to_string() => "other".to_string(),
+        };
+        let normalized = KEYS.pick(&attrs);
+        assert_eq!(
+            normalized,
+            Normalized {
+                instance_id: Some("i-1".to_string()),
+                zone: Some("zone-a".to_string()),
+                ..Normalized::NONE
+            }
+        );
+        assert_eq!(
+            normalized.into_attributes("cloud"),
+            maplit::hashmap! {
+                "PLATFORM".to_string() => "cloud".to_string(),
+                "INSTANCE_ID".to_string() => "i-1".to_string(),
+                "ZONE".to_string() => "zone-a".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_render_env() {
+        let content = render(attributes(), AttributesFormat::Env, DEFAULT_PREFIX, |_| {
+            None
+        })
+        .unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                "AFTERBURN_EMPTY=\n",
+                "AFTERBURN_TAGS=\"env=prod, owner=\\\"ops\\\" \\$HOME \\`id\\` \\\\ it's\nsecond line\"\n",
+                "AFTERBURN_ZONE=us-east-1a\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_shell() {
+        let content = render(
+            attributes(),
+            AttributesFormat::Shell,
+            DEFAULT_PREFIX,
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                "AFTERBURN_EMPTY=\n",
+                "AFTERBURN_TAGS='env=prod, owner=\"ops\" $HOME `id` \\ it'\\''s\nsecond line'\n",
+                "AFTERBURN_ZONE=us-east-1a\n",
+            )
+        );
+
+        // sourcing the file yields the original values
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("attributes");
+        std::fs::write(&path, content).unwrap();
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(r#". "$1" && printf '%s|%s|%s' "$AFTERBURN_EMPTY" "$AFTERBURN_TAGS" "$AFTERBURN_ZONE""#)
+            .arg("sh")
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("|{}|us-east-1a", attributes()["TAGS"])
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        for (pattern, name) in [
+            ("AWS_IPV4_PUBLIC", "AWS_IPV4_PUBLIC"),
+            ("*", ""),
+            ("*_IPV4_PUBLIC*", "DIGITALOCEAN_IPV4_PUBLIC_0"),
+            ("*PUBLIC*", "AWS_PUBLIC_HOSTNAME"),
+            ("GCP_IP_?????_0", "GCP_IP_LOCAL_0"),
+            ("A*B*C", "AxxBxxBxxC"),
+        ] {
+            assert!(glob_match(pattern, name), "{pattern} {name}");
+        }
+        for (pattern, name) in [
+            ("AWS_IPV4_PUBLIC", "AWS_IPV4_PUBLIC_0"),
+            ("*_IPV4_PUBLIC", "AWS_IPV4_LOCAL"),
+            ("GCP_IP_?????_0", "GCP_IP_EXTERNAL_0"),
+            ("A*B*C", "AxxBxxC_"),
+            ("aws_*", "AWS_REGION"),
+        ] {
+            assert!(!glob_match(pattern, name), "{pattern} {name}");
+        }
+    }
+
+    #[test]
+    fn test_render_prefix() {
+        let content = render(
+            maplit::hashmap! { "ZONE".to_string() => "a".to_string() },
+            AttributesFormat::Env,
+            "CLOUD_",
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(content, "CLOUD_ZONE=a\n");
+    }
+
+    #[test]
+    fn test_render_json() {
+        let content = render(attributes(), AttributesFormat::Json, DEFAULT_PREFIX, |_| {
+            None
+        })
+        .unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!({
+                "AFTERBURN_EMPTY": "",
+                "AFTERBURN_TAGS": attributes()["TAGS"],
+                "AFTERBURN_ZONE": "us-east-1a",
+            })
+        );
+        assert!(content.find("AFTERBURN_EMPTY").unwrap() < content.find("AFTERBURN_ZONE").unwrap());
+
+        // declared types are applied, IDs and undeclared values stay strings
+        let content = render(
+            maplit::hashmap! {
+                "INSTANCE_ID".to_string() => "0123".to_string(),
+                "SPOT".to_string() => "true".to_string(),
+                "VCPUS".to_string() => "4".to_string(),
+                "MEMORY".to_string() => "unknown".to_string(),
+                "TAGS".to_string() => "".to_string(),
+                "LABEL".to_string() => "".to_string(),
+                "FLAG".to_string() => "true".to_string(),
+            },
+            AttributesFormat::Json,
+            DEFAULT_PREFIX,
+            |key| match key {
+                "SPOT" => Some(ValueType::Boolean),
+                "VCPUS" | "MEMORY" => Some(ValueType::Number),
+                "TAGS" => Some(ValueType::Nullable),
+                _ => None,
+            },
+        )
+        .unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!({
+                "AFTERBURN_INSTANCE_ID": "0123",
+                "AFTERBURN_SPOT": true,
+                "AFTERBURN_VCPUS": 4,
+                "AFTERBURN_MEMORY": "unknown",
+                "AFTERBURN_TAGS": null,
+                "AFTERBURN_LABEL": "",
+                "AFTERBURN_FLAG": "true",
+            })
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! `agent` CLI sub-command.
+
+use crate::agent::{self, Agent};
+use crate::config::Config;
+use crate::errors::ErrorKind;
+use crate::metadata::{self, Capability};
+use anyhow::{anyhow, Result};
+use clap::{ArgGroup, Parser};
+use std::time::Duration;
+
+/// Keep SSH keys in sync with the metadata service
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("provider-group").args(["cmdline", "detect", "provider"]).required(true)))]
+pub struct CliAgent {
+    /// The name of the cloud provider
+    #[arg(long, value_name = "name")]
+    provider: Option<String>,
+    /// Override scheme, host and port of the provider metadata endpoints
+    #[arg(long, value_name = "url", env = "AFTERBURN_METADATA_URL")]
+    metadata_url: Option<String>,
+    /// Read the cloud provider from the kernel cmdline
+    #[arg(long)]
+    cmdline: bool,
+    /// Read the cloud provider from the kernel cmdline, or detect it
+    #[arg(long)]
+    detect: bool,
+    /// Keep SSH keys in sync for the given user (can be repeated)
+    #[arg(long = "ssh-keys", value_name = "username")]
+    ssh_keys_users: Vec<String>,
+    /// Seconds between synchronizations
+    #[arg(
+        long,
+        value_name = "seconds",
+        default_value_t = agent::DEFAULT_INTERVAL.as_secs(),
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    interval: u64,
+    /// Maximum seconds between synchronizations, when backing off after failures
+    #[arg(
+        long,
+        value_name = "seconds",
+        default_value_t = agent::DEFAULT_MAX_BACKOFF.as_secs(),
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_backoff: u64,
+    #[command(flatten)]
+    pub(crate) log: super::LogArgs,
+}
+
+impl CliAgent {
+    /// Run the `agent` sub-command.
+    pub(crate) fn run(self, config: &Config) -> Result<()> {
+        let provider = super::get_provider(self.provider.as_deref(), self.detect)?;
+        super::with_provider_logger(&provider, || {
+            config.apply_client_settings(&provider, self.metadata_url.as_deref())?;
+
+            if let Some(info) = metadata::lookup_provider(&provider) {
+                if !info.supports(Capability::SshKeys) {
+                    return Err(anyhow!("provider '{}' does not provide SSH keys", provider)
+                        .context(ErrorKind::UnsupportedProvider));
+                }
+            }
+
+            let mut users = self.ssh_keys_users;
+            if users.is_empty() {
+                users.extend(config.outputs.ssh_keys_user.clone());
+            }
+            if users.is_empty() {
+                return Err(
+                    anyhow!("no user specified for SSH keys").context(ErrorKind::InvalidConfig)
+                );
+            }
+
+            Agent::new(&provider, users, config.ssh_keys.clone())
+                .interval(Duration::from_secs(self.interval))
+                .max_backoff(Duration::from_secs(self.max_backoff))
+                .run()
+        })
+    }
+}
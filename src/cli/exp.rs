@@ -1,20 +1,42 @@
 //! `exp` CLI sub-command.
 
-use crate::{initrd, util};
-use anyhow::Result;
+use crate::config::Config;
+use crate::{dump, initrd, metadata, snapshot, util};
+use anyhow::{bail, Context, Result};
 use clap::{ArgGroup, Parser};
+use std::io::Write;
+use std::path::Path;
 
 /// Experimental subcommands
 #[derive(Debug, Parser)]
 pub enum CliExp {
     RdNetworkKargs(CliRdNetworkKargs),
+    Dump(CliDump),
+    Detect(CliDetect),
+    Record(CliRecord),
+    ListProviders(CliListProviders),
 }
 
 impl CliExp {
+    /// Return logging options of the sub-command.
+    pub(crate) fn log_args(&self) -> &super::LogArgs {
+        match self {
+            CliExp::RdNetworkKargs(cmd) => &cmd.log,
+            CliExp::Dump(cmd) => &cmd.log,
+            CliExp::Detect(cmd) => &cmd.log,
+            CliExp::Record(cmd) => &cmd.log,
+            CliExp::ListProviders(cmd) => &cmd.log,
+        }
+    }
+
     // Run sub-command.
-    pub(crate) fn run(&self) -> Result<()> {
+    pub(crate) fn run(&self, config: &Config) -> Result<()> {
         match self {
-            CliExp::RdNetworkKargs(cmd) => cmd.run()?,
+            CliExp::RdNetworkKargs(cmd) => cmd.run(config)?,
+            CliExp::Dump(cmd) => cmd.run(config)?,
+            CliExp::Detect(cmd) => cmd.run()?,
+            CliExp::Record(cmd) => cmd.run(config)?,
+            CliExp::ListProviders(cmd) => cmd.run()?,
         };
         Ok(())
     }
@@ -22,31 +44,208 @@ impl CliExp {
 
 /// Supplement initrd with network configuration kargs
 #[derive(Debug, Parser)]
-#[command(group(ArgGroup::new("provider-group").args(["cmdline", "provider"]).required(true)))]
+#[command(group(ArgGroup::new("provider-group").args(["cmdline", "detect", "provider"]).required(true)))]
 pub struct CliRdNetworkKargs {
     /// Read the cloud provider from the kernel cmdline
     #[arg(long)]
     cmdline: bool,
+    /// Read the cloud provider from the kernel cmdline, or detect it
+    #[arg(long)]
+    detect: bool,
     /// The name of the cloud provider
     #[arg(long, value_name = "name")]
     provider: Option<String>,
+    /// Override scheme, host and port of the provider metadata endpoints
+    #[arg(long, value_name = "url", env = "AFTERBURN_METADATA_URL")]
+    metadata_url: Option<String>,
     /// Default value for network kargs fallback
     #[arg(long = "default-value", value_name = "args")]
     default_kargs: String,
+    #[command(flatten)]
+    log: super::LogArgs,
 }
 
 impl CliRdNetworkKargs {
+    /// Run the sub-command.
+    pub(crate) fn run(&self, config: &Config) -> Result<()> {
+        let provider = super::get_provider(self.provider.as_deref(), self.detect)?;
+        super::with_provider_logger(&provider, || {
+            config.apply_client_settings(&provider, self.metadata_url.as_deref())?;
+
+            if util::has_network_kargs(super::CMDLINE_PATH)? {
+                slog_scope::warn!("kernel cmdline already specifies network arguments, skipping");
+                return Ok(());
+            };
+
+            let provider_kargs = initrd::fetch_network_kargs(&provider)?;
+            let kargs = provider_kargs.as_ref().unwrap_or(&self.default_kargs);
+            initrd::write_network_kargs(kargs)
+        })
+    }
+}
+
+/// Dump all metadata from the cloud provider as JSON
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("provider-group").args(["cmdline", "detect", "provider"]).required(true)))]
+pub struct CliDump {
+    /// Read the cloud provider from the kernel cmdline
+    #[arg(long)]
+    cmdline: bool,
+    /// Read the cloud provider from the kernel cmdline, or detect it
+    #[arg(long)]
+    detect: bool,
+    /// The name of the cloud provider
+    #[arg(long, value_name = "name")]
+    provider: Option<String>,
+    /// Override scheme, host and port of the provider metadata endpoints
+    #[arg(long, value_name = "url", env = "AFTERBURN_METADATA_URL")]
+    metadata_url: Option<String>,
+    #[command(flatten)]
+    log: super::LogArgs,
+}
+
+impl CliDump {
+    /// Run the sub-command.
+    pub(crate) fn run(&self, config: &Config) -> Result<()> {
+        let provider = super::get_provider(self.provider.as_deref(), self.detect)?;
+        super::with_provider_logger(&provider, || {
+            config.apply_client_settings(&provider, self.metadata_url.as_deref())?;
+            let metadata =
+                metadata::fetch_metadata(&provider).context("fetching metadata from provider")?;
+
+            let dump =
+                dump::MetadataDump::collect(&provider, metadata.as_ref(), &config.attributes);
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &dump).context("failed to serialize dump")?;
+            writeln!(stdout).context("failed to write dump")?;
+            Ok(())
+        })
+    }
+}
+
+/// Detect the cloud provider from local signals
+#[derive(Debug, Parser)]
+pub struct CliDetect {
+    #[command(flatten)]
+    log: super::LogArgs,
+}
+
+impl CliDetect {
     /// Run the sub-command.
     pub(crate) fn run(&self) -> Result<()> {
-        let provider = super::get_provider(self.provider.as_deref())?;
+        let guesses = util::Detector::default().detect();
+        if guesses.is_empty() {
+            bail!("unable to detect platform from local signals");
+        }
 
-        if util::has_network_kargs(super::CMDLINE_PATH)? {
-            slog_scope::warn!("kernel cmdline already specifies network arguments, skipping");
+        let mut stdout = std::io::stdout().lock();
+        for guess in guesses {
+            writeln!(
+                stdout,
+                "{} (confidence {})",
+                guess.platform, guess.confidence
+            )
+            .context("failed to write detected platform")?;
+            for evidence in guess.evidence {
+                writeln!(stdout, "  - {evidence}").context("failed to write detected platform")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Record all metadata from the cloud provider into a snapshot directory
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("provider-group").args(["cmdline", "detect", "provider"]).required(true)))]
+pub struct CliRecord {
+    /// Read the cloud provider from the kernel cmdline
+    #[arg(long)]
+    cmdline: bool,
+    /// Read the cloud provider from the kernel cmdline, or detect it
+    #[arg(long)]
+    detect: bool,
+    /// The name of the cloud provider
+    #[arg(long, value_name = "name")]
+    provider: Option<String>,
+    /// Override scheme, host and port of the provider metadata endpoints
+    #[arg(long, value_name = "url", env = "AFTERBURN_METADATA_URL")]
+    metadata_url: Option<String>,
+    /// The directory into which the snapshot is written
+    #[arg(long = "snapshot-dir", value_name = "path")]
+    snapshot_dir: String,
+    #[command(flatten)]
+    log: super::LogArgs,
+}
+
+impl CliRecord {
+    /// Run the sub-command.
+    pub(crate) fn run(&self, config: &Config) -> Result<()> {
+        let provider = super::get_provider(self.provider.as_deref(), self.detect)?;
+        super::with_provider_logger(&provider, || {
+            config.apply_client_settings(&provider, self.metadata_url.as_deref())?;
+            let metadata =
+                metadata::fetch_metadata(&provider).context("fetching metadata from provider")?;
+
+            let mut snapshot = snapshot::Snapshot::capture(&provider, metadata.as_ref())
+                .context("capturing metadata snapshot")?;
+            snapshot.filter_attributes(&config.attributes);
+            snapshot.redact();
+            snapshot
+                .write_to(Path::new(&self.snapshot_dir))
+                .context("writing metadata snapshot")?;
+            slog_scope::info!("recorded metadata snapshot to {}", self.snapshot_dir);
+            Ok(())
+        })
+    }
+}
+
+/// List supported cloud providers and their capabilities
+#[derive(Debug, Parser)]
+pub struct CliListProviders {
+    /// Print the capability matrix as JSON
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    log: super::LogArgs,
+}
+
+impl CliListProviders {
+    /// Run the sub-command.
+    pub(crate) fn run(&self) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        if self.json {
+            serde_json::to_writer_pretty(&mut stdout, metadata::PROVIDERS)
+                .context("failed to serialize providers")?;
+            writeln!(stdout).context("failed to write providers")?;
             return Ok(());
-        };
+        }
+
+        let id_width = metadata::PROVIDERS
+            .iter()
+            .map(|p| p.id.len())
+            .max()
+            .unwrap_or_default();
+        write!(stdout, "{:id_width$}", "PROVIDER").context("failed to write providers")?;
+        for cap in metadata::Capability::ALL {
+            write!(stdout, "  {}", cap.name().to_uppercase())
+                .context("failed to write providers")?;
+        }
+        writeln!(stdout, "  ALIASES").context("failed to write providers")?;
 
-        let provider_kargs = initrd::fetch_network_kargs(&provider)?;
-        let kargs = provider_kargs.as_ref().unwrap_or(&self.default_kargs);
-        initrd::write_network_kargs(kargs)
+        for info in metadata::PROVIDERS {
+            write!(stdout, "{:id_width$}", info.id).context("failed to write providers")?;
+            for cap in metadata::Capability::ALL {
+                let mark = if info.supports(*cap) { "yes" } else { "-" };
+                write!(stdout, "  {:width$}", mark, width = cap.name().len())
+                    .context("failed to write providers")?;
+            }
+            let aliases = if info.aliases.is_empty() {
+                "-".to_string()
+            } else {
+                info.aliases.join(",")
+            };
+            writeln!(stdout, "  {aliases}").context("failed to write providers")?;
+        }
+        Ok(())
     }
 }
@@ -1,9 +1,13 @@
 //! Command-line arguments parsing.
 
-use anyhow::Result;
-use clap::Parser;
-use slog_scope::trace;
+use crate::config::Config;
+use crate::errors::ErrorKind;
+use crate::logging::LogFormat;
+use anyhow::{Context, Result};
+use clap::{Args, Parser};
+use slog_scope::debug;
 
+mod agent;
 mod exp;
 mod multi;
 
@@ -19,18 +23,38 @@ pub(crate) enum CliConfig {
     Multi(multi::CliMulti),
     #[clap(subcommand)]
     Exp(exp::CliExp),
+    Agent(agent::CliAgent),
 }
 
 impl CliConfig {
+    /// Return the requested log format.
+    pub fn log_format(&self) -> LogFormat {
+        match self {
+            CliConfig::Multi(cmd) => cmd.log.log_format,
+            CliConfig::Exp(cmd) => cmd.log_args().log_format,
+            CliConfig::Agent(cmd) => cmd.log.log_format,
+        }
+    }
+
     /// Run the relevant CLI sub-command.
     pub fn run(self) -> Result<()> {
+        let config = Config::load().context("loading configuration")?;
         match self {
-            CliConfig::Multi(cmd) => cmd.run(),
-            CliConfig::Exp(cmd) => cmd.run(),
+            CliConfig::Multi(cmd) => cmd.run(&config),
+            CliConfig::Exp(cmd) => cmd.run(&config),
+            CliConfig::Agent(cmd) => cmd.run(&config),
         }
     }
 }
 
+/// Logging options, shared by all sub-commands.
+#[derive(Clone, Debug, Default, Args)]
+pub(crate) struct LogArgs {
+    /// Log output format
+    #[arg(long, value_enum, value_name = "format", default_value_t)]
+    log_format: LogFormat,
+}
+
 /// Parse command-line arguments into CLI configuration.
 pub(crate) fn parse_args(argv: impl IntoIterator<Item = String>) -> Result<CliConfig> {
     let args = translate_legacy_args(argv);
@@ -38,19 +62,32 @@ pub(crate) fn parse_args(argv: impl IntoIterator<Item = String>) -> Result<CliCo
         Err(e) if e.kind() == clap::error::ErrorKind::DisplayHelp => e.exit(),
         Err(e) if e.kind() == clap::error::ErrorKind::DisplayVersion => e.exit(),
         v => v,
-    }?;
-    trace!("cli configuration - {:?}", cfg);
+    }
+    .context(ErrorKind::InvalidConfig)?;
     Ok(cfg)
 }
 
 /// Return specified provider or parse provider ID from kargs.
-fn get_provider(provider: Option<&str>) -> Result<String> {
+///
+/// If `detect` is set and kargs do not specify a provider, local signals
+/// are inspected to guess it.
+fn get_provider(provider: Option<&str>, detect: bool) -> Result<String> {
     match provider {
         Some(p) => Ok(p.to_string()),
+        None if detect => crate::util::get_platform(CMDLINE_PATH).or_else(|e| {
+            debug!("{:#}, falling back to platform detection", e);
+            crate::util::detect_platform()
+        }),
         None => crate::util::get_platform(CMDLINE_PATH),
     }
 }
 
+/// Run `f` with the provider name attached to all log records.
+fn with_provider_logger<T>(provider: &str, f: impl FnOnce() -> T) -> T {
+    let logger = slog_scope::logger().new(slog::slog_o!("provider" => provider.to_string()));
+    slog_scope::scope(&logger, f)
+}
+
 /// Translate command-line arguments from legacy mode.
 ///
 /// In legacy mode there are no sub-commands, and single-dash (Golang-style)
@@ -189,6 +226,212 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_exp_dump_cmd() {
+        let args: Vec<_> = ["afterburn", "exp", "dump", "--provider", "aws"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        let cmd = parse_args(args).unwrap();
+        match cmd {
+            CliConfig::Exp(exp::CliExp::Dump(_)) => {}
+            x => panic!("unexpected cmd: {x:?}"),
+        };
+
+        // A provider source is required.
+        let args: Vec<_> = ["afterburn", "exp", "dump"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        parse_args(args).unwrap_err();
+    }
+
+    #[test]
+    fn test_detect_flag() {
+        let args: Vec<_> = [
+            "afterburn",
+            "multi",
+            "--detect",
+            "--attributes",
+            "/dev/null",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        parse_args(args).unwrap();
+
+        // Mutually exclusive with other provider sources.
+        let args: Vec<_> = ["afterburn", "multi", "--detect", "--provider", "aws"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        parse_args(args).unwrap_err();
+    }
+
+    #[test]
+    fn test_cache_flags() {
+        let args: Vec<_> = [
+            "afterburn",
+            "multi",
+            "--cmdline",
+            "--cache",
+            "--cache-max-age",
+            "60",
+            "--refresh",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        parse_args(args).unwrap();
+
+        // Cache tuning requires the cache to be enabled.
+        let args: Vec<_> = ["afterburn", "multi", "--cmdline", "--refresh"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        parse_args(args).unwrap_err();
+    }
+
+    #[test]
+    fn test_exp_record_cmd() {
+        let args: Vec<_> = [
+            "afterburn",
+            "exp",
+            "record",
+            "--provider",
+            "aws",
+            "--snapshot-dir",
+            "/tmp/snapshot",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+        let cmd = parse_args(args).unwrap();
+        match cmd {
+            CliConfig::Exp(exp::CliExp::Record(_)) => {}
+            x => panic!("unexpected cmd: {x:?}"),
+        };
+    }
+
+    #[test]
+    fn test_metadata_url() {
+        let args: Vec<_> = [
+            "afterburn",
+            "multi",
+            "--provider",
+            "aws",
+            "--metadata-url",
+            "http://127.0.0.1:8080",
+            "--attributes",
+            "/dev/null",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+        let cmd = parse_args(args).unwrap();
+        match cmd {
+            CliConfig::Multi(_) => {}
+            x => panic!("unexpected cmd: {x:?}"),
+        };
+    }
+
+    #[test]
+    fn test_dry_run_flag() {
+        let args: Vec<_> = ["afterburn", "multi", "--cmdline", "--dry-run", "--check-in"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        parse_args(args).unwrap();
+
+        let args: Vec<_> = ["afterburn", "multi", "--cmdline", "--dry-run", "--cache"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        parse_args(args).unwrap_err();
+    }
+
+    #[test]
+    fn test_log_format() {
+        let args: Vec<_> = ["afterburn", "exp", "detect", "--log-format", "journal"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let cmd = parse_args(args).unwrap();
+        assert_eq!(cmd.log_format(), LogFormat::Journal);
+
+        let args: Vec<_> = ["afterburn", "--cmdline", "--log-format=json"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let cmd = parse_args(args).unwrap();
+        assert_eq!(cmd.log_format(), LogFormat::Json);
+
+        let args: Vec<_> = ["afterburn", "multi", "--cmdline"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let cmd = parse_args(args).unwrap();
+        assert_eq!(cmd.log_format(), LogFormat::Term);
+    }
+
+    #[test]
+    fn test_exp_list_providers_cmd() {
+        let args: Vec<_> = ["afterburn", "exp", "list-providers", "--json"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        let cmd = parse_args(args).unwrap();
+        match cmd {
+            CliConfig::Exp(exp::CliExp::ListProviders(_)) => {}
+            x => panic!("unexpected cmd: {x:?}"),
+        };
+    }
+
+    #[test]
+    fn test_agent_cmd() {
+        let args: Vec<_> = [
+            "afterburn",
+            "agent",
+            "--provider",
+            "aws",
+            "--ssh-keys",
+            "core",
+            "--ssh-keys",
+            "admin",
+            "--interval",
+            "60",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+        let cmd = parse_args(args).unwrap();
+        match cmd {
+            CliConfig::Agent(_) => {}
+            x => panic!("unexpected cmd: {x:?}"),
+        };
+
+        // a provider is required
+        let args: Vec<_> = ["afterburn", "agent", "--ssh-keys", "core"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        parse_args(args).unwrap_err();
+
+        // delays must be positive, not to busy-loop
+        for flag in ["--interval", "--max-backoff"] {
+            let args: Vec<_> = ["afterburn", "agent", "--provider", "aws", flag, "0"]
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            parse_args(args).unwrap_err();
+        }
+    }
+
     #[test]
     fn test_default_net_kargs() {
         // Missing flag.
@@ -1,28 +1,47 @@
 //! `multi` CLI sub-command.
 
-use crate::metadata;
+use crate::attributes::AttributesFormat;
+use crate::config::Config;
+use crate::{dryrun, metadata, providers, snapshot};
 use anyhow::{Context, Result};
 use clap::{ArgGroup, Parser};
+use std::path::Path;
+use std::time::Duration;
 
 /// Perform multiple tasks in a single call
 #[derive(Debug, Parser)]
-#[command(group(ArgGroup::new("provider-group").args(["cmdline", "provider"]).required(true)))]
+#[command(group(ArgGroup::new("provider-group").args(["cmdline", "detect", "provider"]).required(true)))]
 pub struct CliMulti {
     /// The name of the cloud provider
     #[arg(long, value_name = "name")]
     provider: Option<String>,
+    /// Override scheme, host and port of the provider metadata endpoints
+    #[arg(long, value_name = "url", env = "AFTERBURN_METADATA_URL")]
+    metadata_url: Option<String>,
     /// Read the cloud provider from the kernel cmdline
     #[arg(long)]
     cmdline: bool,
+    /// Read the cloud provider from the kernel cmdline, or detect it
+    #[arg(long)]
+    detect: bool,
     /// The file into which the metadata attributes are written
     #[arg(long = "attributes", value_name = "path")]
     attributes_file: Option<String>,
+    /// The format of the attributes file
+    #[arg(long, value_enum, value_name = "format")]
+    attributes_format: Option<AttributesFormat>,
+    /// Also write provider-agnostic attributes, e.g. AFTERBURN_REGION
+    #[arg(long)]
+    normalized_attributes: bool,
     /// Check-in this instance boot with the cloud provider
     #[arg(long)]
     check_in: bool,
     /// The file into which the hostname should be written
     #[arg(long = "hostname", value_name = "path")]
     hostname_file: Option<String>,
+    /// The file into which a kubelet systemd drop-in is written
+    #[arg(long = "kubelet-config", value_name = "path")]
+    kubelet_config_file: Option<String>,
     /// The directory into which network units are written
     #[arg(long = "network-units", value_name = "path")]
     network_units_dir: Option<String>,
@@ -32,6 +51,23 @@ pub struct CliMulti {
     /// Update SSH keys for the given user
     #[arg(long = "ssh-keys", value_name = "username")]
     ssh_keys_user: Option<String>,
+    /// Share fetched metadata with other invocations, via a cache in /run/afterburn
+    #[arg(long)]
+    cache: bool,
+    /// Ignore cached metadata older than the given number of seconds
+    #[arg(long, value_name = "seconds", requires = "cache")]
+    cache_max_age: Option<u64>,
+    /// Ignore cached metadata, fetching it again from the provider
+    #[arg(long, requires = "cache")]
+    refresh: bool,
+    /// Report intended writes without touching the system
+    #[arg(long, conflicts_with = "cache")]
+    dry_run: bool,
+    /// Run all actions even if some of them fail, reporting failures at the end
+    #[arg(long)]
+    keep_going: bool,
+    #[command(flatten)]
+    pub(crate) log: super::LogArgs,
     /// Whether this command was translated from legacy CLI args
     #[arg(long, hide = true)]
     legacy_cli: bool,
@@ -39,55 +75,281 @@ pub struct CliMulti {
 
 impl CliMulti {
     /// Run the `multi` sub-command.
-    pub(crate) fn run(self) -> Result<()> {
-        let provider = super::get_provider(self.provider.as_deref())?;
-
-        if self.attributes_file.is_none()
-            && self.network_units_dir.is_none()
-            && self.netplan_config_dir.is_none()
-            && !self.check_in
-            && self.ssh_keys_user.is_none()
-            && self.hostname_file.is_none()
-        {
-            slog_scope::warn!("multi: no action specified");
+    pub(crate) fn run(self, config: &Config) -> Result<()> {
+        let provider = super::get_provider(self.provider.as_deref(), self.detect)?;
+        super::with_provider_logger(&provider, || {
+            config.apply_client_settings(&provider, self.metadata_url.as_deref())?;
+
+            // fill in outputs not specified on the command-line from configuration
+            let outputs = &config.outputs;
+            let attributes_file = self.attributes_file.or_else(|| outputs.attributes.clone());
+            let attributes_format = self
+                .attributes_format
+                .or(outputs.attributes_format)
+                .unwrap_or_default();
+            let normalized_attributes = self.normalized_attributes || outputs.normalized_attributes;
+            let ssh_keys_user = self.ssh_keys_user.or_else(|| outputs.ssh_keys_user.clone());
+            let hostname_file = self.hostname_file.or_else(|| outputs.hostname.clone());
+            let kubelet_config_file = self
+                .kubelet_config_file
+                .or_else(|| outputs.kubelet_config.clone());
+            let network_units_dir = self
+                .network_units_dir
+                .or_else(|| outputs.network_units.clone());
+            let netplan_config_dir = self
+                .netplan_config_dir
+                .or_else(|| outputs.netplan_config.clone());
+            let check_in = self.check_in || outputs.check_in;
+
+            if attributes_file.is_none()
+                && network_units_dir.is_none()
+                && netplan_config_dir.is_none()
+                && !check_in
+                && ssh_keys_user.is_none()
+                && hostname_file.is_none()
+                && kubelet_config_file.is_none()
+            {
+                slog_scope::warn!("multi: no action specified");
+            }
+
+            // fetch the metadata from the configured provider
+            let metadata = if self.cache {
+                snapshot::SnapshotCache::new(snapshot::CACHE_DIR)
+                    .max_age(self.cache_max_age.map(Duration::from_secs))
+                    .refresh(self.refresh)
+                    .fetch_metadata(&provider)
+            } else {
+                metadata::fetch_metadata(&provider)
+            }
+            .context("fetching metadata from provider")?;
+            // replayed metadata are interpreted for their original provider
+            let platform = metadata.recorded_provider().unwrap_or(&provider);
+            let normalized_platform = normalized_attributes.then_some(platform);
+
+            let writer = outputs.writer()?;
+
+            if self.dry_run {
+                let mut plan = dryrun::Plan::default();
+
+                if let Some(path) = attributes_file {
+                    let content = metadata
+                        .render_attributes(
+                            attributes_format,
+                            normalized_platform,
+                            &config.attributes,
+                        )
+                        .context("rendering metadata attributes")?;
+                    plan.add(path, Some(content), &writer)?;
+                }
+
+                if let Some(username) = ssh_keys_user {
+                    let content = metadata
+                        .render_ssh_keys(&config.ssh_keys)
+                        .context("fetching ssh keys")?;
+                    let user = providers::get_user(&username)?;
+                    plan.add(
+                        providers::ssh_keys_path(&user),
+                        content,
+                        &providers::ssh_keys_writer(&user),
+                    )?;
+                }
+
+                if let Some(path) = hostname_file {
+                    if let Some(hostname) =
+                        metadata.render_hostname().context("rendering hostname")?
+                    {
+                        plan.add(path, Some(format!("{hostname}\n")), &writer)?;
+                    }
+                }
+
+                if let Some(path) = kubelet_config_file {
+                    let content = metadata
+                        .render_kubelet_config(platform)
+                        .context("rendering kubelet config")?;
+                    plan.add(path, Some(content), &writer)?;
+                }
+
+                if let Some(dir) = network_units_dir {
+                    let units = metadata
+                        .render_network_units()
+                        .context("rendering network units")?;
+                    for (unit_name, content) in units {
+                        plan.add(Path::new(&dir).join(unit_name), Some(content), &writer)?;
+                    }
+                }
+
+                if let Some(dir) = netplan_config_dir {
+                    if let Some((file_name, content)) = metadata
+                        .render_netplan_config()
+                        .context("rendering netplan config")?
+                    {
+                        plan.add(Path::new(&dir).join(file_name), Some(content), &writer)?;
+                    }
+                }
+
+                plan.check_in = check_in;
+                return plan.report(&mut std::io::stdout().lock());
+            }
+
+            let mut actions = Actions::new(self.keep_going);
+
+            // write attributes if configured to do so
+            if let Some(path) = attributes_file {
+                actions.run("attributes", || {
+                    metadata
+                        .write_attributes(
+                            path,
+                            attributes_format,
+                            normalized_platform,
+                            &config.attributes,
+                            &writer,
+                        )
+                        .context("writing metadata attributes")
+                })?;
+            }
+
+            // write ssh keys if configured to do so
+            if let Some(username) = ssh_keys_user {
+                actions.run("ssh-keys", || {
+                    metadata
+                        .write_ssh_keys(username, &config.ssh_keys)
+                        .context("writing ssh keys")
+                })?;
+            }
+
+            // write hostname if configured to do so
+            if let Some(path) = hostname_file {
+                actions.run("hostname", || {
+                    metadata
+                        .write_hostname(path, &writer)
+                        .context("writing hostname")
+                })?;
+            }
+
+            // write kubelet config if configured to do so
+            if let Some(path) = kubelet_config_file {
+                actions.run("kubelet-config", || {
+                    metadata
+                        .write_kubelet_config(path, platform, &writer)
+                        .context("writing kubelet config")
+                })?;
+            }
+
+            // write network units if configured to do so
+            if let Some(dir) = network_units_dir {
+                actions.run("network-units", || {
+                    metadata
+                        .write_network_units(dir, &writer)
+                        .context("writing network units")
+                })?;
+            }
+
+            // write netplan config if configured to do so
+            if let Some(dir) = netplan_config_dir {
+                actions.run("netplan-config", || {
+                    metadata
+                        .write_netplan_config(dir, &writer)
+                        .context("writing netplan config")
+                })?;
+            }
+
+            // perform boot check-in.
+            if check_in {
+                actions.run("check-in", || {
+                    metadata
+                        .boot_checkin()
+                        .context("checking-in instance boot to cloud provider")
+                })?;
+            }
+
+            actions.finish()
+        })
+    }
+}
+
+/// Runner for `multi` actions, optionally continuing after failures.
+#[derive(Debug)]
+struct Actions {
+    keep_going: bool,
+    total: usize,
+    failed: Vec<(&'static str, anyhow::Error)>,
+}
+
+impl Actions {
+    fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            total: 0,
+            failed: Vec::new(),
         }
+    }
 
-        // fetch the metadata from the configured provider
-        let metadata =
-            metadata::fetch_metadata(&provider).context("fetching metadata from provider")?;
-
-        // write attributes if configured to do so
-        self.attributes_file
-            .map_or(Ok(()), |x| metadata.write_attributes(x))
-            .context("writing metadata attributes")?;
-
-        // write ssh keys if configured to do so
-        self.ssh_keys_user
-            .map_or(Ok(()), |x| metadata.write_ssh_keys(x))
-            .context("writing ssh keys")?;
-
-        // write hostname if configured to do so
-        self.hostname_file
-            .map_or(Ok(()), |x| metadata.write_hostname(x))
-            .context("writing hostname")?;
-
-        // write network units if configured to do so
-        self.network_units_dir
-            .map_or(Ok(()), |x| metadata.write_network_units(x))
-            .context("writing network units")?;
-
-        // write netplan config if configured to do so
-        self.netplan_config_dir
-            .map_or(Ok(()), |x| metadata.write_netplan_config(x))
-            .context("writing netplan config")?;
-
-        // perform boot check-in.
-        if self.check_in {
-            metadata
-                .boot_checkin()
-                .context("checking-in instance boot to cloud provider")?;
+    /// Run an action. In keep-going mode, failures are logged and recorded
+    /// instead of being returned.
+    fn run(&mut self, name: &'static str, action: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.total += 1;
+        match action() {
+            Ok(()) => Ok(()),
+            Err(e) if self.keep_going => {
+                slog_scope::error!("action failed: {:#}", e; "action" => name);
+                self.failed.push((name, e));
+                Ok(())
+            }
+            Err(e) => Err(e),
         }
+    }
+
+    /// Summarize recorded failures.
+    ///
+    /// The first failure is returned, so that its error kind selects the
+    /// exit code.
+    fn finish(self) -> Result<()> {
+        let names: Vec<_> = self.failed.iter().map(|(name, _)| *name).collect();
+        let total = self.total;
+        match self.failed.into_iter().next() {
+            None => Ok(()),
+            Some((_, e)) => Err(e.context(format!(
+                "{} of {} actions failed: {}",
+                names.len(),
+                total,
+                names.join(", ")
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_actions_keep_going() {
+        let mut actions = Actions::new(true);
+        actions.run("attributes", || Ok(())).unwrap();
+        actions
+            .run("ssh-keys", || Err(anyhow!("unreachable")))
+            .unwrap();
+        let mut ran = false;
+        actions
+            .run("hostname", || {
+                ran = true;
+                Err(anyhow!("permission denied"))
+            })
+            .unwrap();
+        assert!(ran);
+
+        let err = actions.finish().unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "2 of 3 actions failed: ssh-keys, hostname: unreachable"
+        );
+
+        let mut actions = Actions::new(false);
+        actions
+            .run("ssh-keys", || Err(anyhow!("unreachable")))
+            .unwrap_err();
 
-        Ok(())
+        Actions::new(true).finish().unwrap();
     }
 }
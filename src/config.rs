@@ -0,0 +1,529 @@
+//! Configuration file.
+//!
+//! Defaults for command-line flags and tunables for metadata fetching can be
+//! set in `/etc/afterburn/afterburn.toml`. Drop-ins in `/etc/afterburn/afterburn.d/`
+//! are merged on top of it, in lexicographic order. Command-line flags always
+//! take precedence over configuration values.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use openssh_keys::PublicKey;
+use serde::Deserialize;
+use slog_scope::{debug, warn};
+
+use crate::attributes::{self, AttributesFormat};
+use crate::errors::ErrorKind;
+use crate::retry;
+use crate::util::{AtomicWrite, FileOwner};
+
+/// Path to the main configuration file.
+pub(crate) const CONFIG_PATH: &str = "/etc/afterburn/afterburn.toml";
+
+/// Directory holding configuration drop-ins.
+pub(crate) const CONFIG_DROPIN_DIR: &str = "/etc/afterburn/afterburn.d";
+
+/// Environment variable overriding the configuration directory.
+pub(crate) const CONFIG_DIR_ENV: &str = "AFTERBURN_CONFIG_DIR";
+
+/// Afterburn configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Config {
+    /// Attributes policy.
+    pub attributes: AttributesConfig,
+    /// Default outputs for the `multi` sub-command.
+    pub outputs: OutputsConfig,
+    /// Retry policy for metadata fetching.
+    pub retry: RetryConfig,
+    /// SSH keys policy.
+    pub ssh_keys: SshKeysConfig,
+    /// Per-provider settings, by provider name.
+    pub providers: HashMap<String, ProviderConfig>,
+}
+
+/// Default outputs, used when not specified on the command-line.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct OutputsConfig {
+    pub attributes: Option<String>,
+    pub attributes_format: Option<AttributesFormat>,
+    pub check_in: bool,
+    /// Permissions of written files, e.g. `0o640`.
+    pub file_mode: Option<u32>,
+    /// Owner of written files, as `user` or `user:group`.
+    pub file_owner: Option<String>,
+    pub hostname: Option<String>,
+    pub kubelet_config: Option<String>,
+    pub network_units: Option<String>,
+    pub netplan_config: Option<String>,
+    /// Also write provider-agnostic attributes.
+    pub normalized_attributes: bool,
+    pub ssh_keys_user: Option<String>,
+}
+
+/// Retry policy, overriding built-in defaults.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct RetryConfig {
+    /// Maximum number of retries for each request.
+    pub max_retries: Option<u8>,
+    /// Initial backoff between retries, in seconds.
+    pub initial_backoff: Option<f64>,
+    /// Maximum backoff between retries, in seconds.
+    pub max_backoff: Option<f64>,
+    /// Whether to randomize backoff delays.
+    pub jitter: Option<bool>,
+    /// Timeout for each request attempt, in seconds.
+    pub request_timeout: Option<f64>,
+    /// Deadline for all requests and retries in a run, in seconds.
+    pub deadline: Option<f64>,
+}
+
+/// Attributes policy, for the attributes file and dumps.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct AttributesConfig {
+    /// Globs of attributes to keep; all attributes are kept if unset.
+    pub include: Option<Vec<String>>,
+    /// Globs of attributes to drop, even if included.
+    pub exclude: Vec<String>,
+    /// Prefix of names in the attributes file, instead of `AFTERBURN_`.
+    pub prefix: Option<String>,
+}
+
+/// SSH keys policy.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct SshKeysConfig {
+    /// Allowed key types (e.g. `ssh-ed25519`); all types are allowed if unset.
+    pub allowed_types: Option<Vec<String>>,
+}
+
+/// Provider-specific settings.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct ProviderConfig {
+    /// Base URL replacing scheme, host and port of metadata endpoints.
+    pub base_url: Option<String>,
+    /// PEM bundle of extra CA certificates for HTTPS metadata endpoints.
+    pub ca_bundle: Option<PathBuf>,
+    /// Allowed SHA-256 digests of the server public key, as `sha256//<base64>`.
+    pub spki_pins: Option<Vec<String>>,
+}
+
+impl OutputsConfig {
+    /// Build the writer for output files.
+    pub(crate) fn writer(&self) -> Result<AtomicWrite> {
+        let mut writer = AtomicWrite::default();
+        if let Some(mode) = self.file_mode {
+            if mode > 0o7777 {
+                return Err(anyhow!("invalid outputs.file-mode {:#o}", mode))
+                    .context(ErrorKind::InvalidConfig);
+            }
+            writer = writer.mode(mode);
+        }
+        if let Some(owner) = &self.file_owner {
+            let owner = FileOwner::parse(owner)
+                .with_context(|| format!("invalid outputs.file-owner '{owner}'"))
+                .context(ErrorKind::InvalidConfig)?;
+            writer = writer.owner(Some(owner));
+        }
+        Ok(writer)
+    }
+}
+
+impl Config {
+    /// Load configuration from the default locations.
+    ///
+    /// If `AFTERBURN_CONFIG_DIR` is set, `afterburn.toml` and `afterburn.d/`
+    /// are looked up in that directory instead of `/etc/afterburn`.
+    pub(crate) fn load() -> Result<Self> {
+        match std::env::var_os(CONFIG_DIR_ENV) {
+            Some(dir) => {
+                let dir = PathBuf::from(dir);
+                Self::load_from(&dir.join("afterburn.toml"), &dir.join("afterburn.d"))
+            }
+            None => Self::load_from(Path::new(CONFIG_PATH), Path::new(CONFIG_DROPIN_DIR)),
+        }
+    }
+
+    /// Load configuration from a main file and a drop-ins directory.
+    ///
+    /// Both are optional. Drop-ins are merged on top of the main file, with
+    /// nested tables merged key by key.
+    fn load_from(path: &Path, dropin_dir: &Path) -> Result<Self> {
+        let mut merged = toml::Table::new();
+
+        let mut paths = vec![path.to_path_buf()];
+        paths.extend(list_dropins(dropin_dir)?);
+        for path in paths {
+            let content = match fs::read_to_string(&path) {
+                Ok(c) => c,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("failed to read {path:?}")),
+            };
+            debug!("loading configuration from {:?}", path);
+            let table: toml::Table = toml::from_str(&content)
+                .with_context(|| format!("failed to parse {path:?}"))
+                .context(ErrorKind::InvalidConfig)?;
+            merge_tables(&mut merged, table);
+        }
+
+        toml::Value::Table(merged)
+            .try_into()
+            .context(ErrorKind::InvalidConfig)
+    }
+
+    /// Apply process-wide settings for metadata fetching from the given provider.
+    ///
+    /// A base URL given on the command-line takes precedence over the
+    /// provider one from configuration.
+    pub(crate) fn apply_client_settings(
+        &self,
+        provider: &str,
+        base_url: Option<&str>,
+    ) -> Result<()> {
+        let mut policy = retry::Retry::new();
+        let mut custom_policy = false;
+        if let Some(retries) = self.retry.max_retries {
+            policy = policy.max_retries(retries);
+            custom_policy = true;
+        }
+        if let Some(secs) = self.retry.initial_backoff {
+            policy = policy.initial_backoff(parse_secs(secs, "retry.initial-backoff")?);
+            custom_policy = true;
+        }
+        if let Some(secs) = self.retry.max_backoff {
+            policy = policy.max_backoff(parse_secs(secs, "retry.max-backoff")?);
+            custom_policy = true;
+        }
+        if let Some(jitter) = self.retry.jitter {
+            policy = policy.jitter(jitter);
+            custom_policy = true;
+        }
+        if custom_policy {
+            retry::set_default_policy(policy).context(ErrorKind::InvalidConfig)?;
+        }
+        if let Some(secs) = self.retry.request_timeout {
+            let timeout = parse_secs(secs, "retry.request-timeout")?;
+            retry::set_request_timeout(timeout).context(ErrorKind::InvalidConfig)?;
+        }
+        if let Some(secs) = self.retry.deadline {
+            retry::set_deadline(parse_secs(secs, "retry.deadline")?);
+        }
+
+        let provider_config = self.providers.get(provider);
+        if let Some(base_url) =
+            base_url.or_else(|| provider_config.and_then(|p| p.base_url.as_deref()))
+        {
+            debug!("using base URL '{}' for provider '{}'", base_url, provider);
+            retry::set_base_url_override(base_url).context(ErrorKind::InvalidConfig)?;
+        }
+
+        if let Some(p) = provider_config {
+            if p.ca_bundle.is_some() || p.spki_pins.is_some() {
+                let mut tls = retry::TlsSettings::default();
+                if let Some(path) = &p.ca_bundle {
+                    tls = tls.ca_bundle(path).context(ErrorKind::InvalidConfig)?;
+                }
+                if let Some(pins) = &p.spki_pins {
+                    tls = tls.spki_pins(pins).context(ErrorKind::InvalidConfig)?;
+                }
+                retry::set_tls_settings(tls).context(ErrorKind::InvalidConfig)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AttributesConfig {
+    /// Filter attributes according to policy, matching globs against
+    /// unprefixed names.
+    pub(crate) fn filter(&self, attributes: HashMap<String, String>) -> HashMap<String, String> {
+        attributes
+            .into_iter()
+            .filter(|(key, _)| self.keeps(key))
+            .collect()
+    }
+
+    /// Whether the attribute named `key` is kept.
+    pub(crate) fn keeps(&self, key: &str) -> bool {
+        let matches = |globs: &[String]| globs.iter().any(|g| attributes::glob_match(g, key));
+        self.include.as_deref().is_none_or(matches) && !matches(&self.exclude)
+    }
+
+    /// Return the prefix of names in the attributes file.
+    pub(crate) fn prefix(&self) -> Result<&str> {
+        let prefix = match &self.prefix {
+            Some(prefix) => prefix,
+            None => return Ok(attributes::DEFAULT_PREFIX),
+        };
+        // names must remain valid shell and environment variable names
+        let valid = prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !prefix.starts_with(|c: char| c.is_ascii_digit());
+        if !valid {
+            return Err(anyhow!("invalid attributes.prefix '{}'", prefix))
+                .context(ErrorKind::InvalidConfig);
+        }
+        Ok(prefix)
+    }
+}
+
+impl SshKeysConfig {
+    /// Filter SSH keys according to policy.
+    pub(crate) fn filter_keys(&self, keys: Vec<PublicKey>) -> Vec<PublicKey> {
+        let allowed = match &self.allowed_types {
+            Some(types) => types,
+            None => return keys,
+        };
+        keys.into_iter()
+            .filter(|key| {
+                let keep = allowed.iter().any(|t| t == key.keytype());
+                if !keep {
+                    warn!("ignoring ssh key with disallowed type '{}'", key.keytype());
+                }
+                keep
+            })
+            .collect()
+    }
+}
+
+/// Parse a duration in seconds from a configuration value.
+fn parse_secs(secs: f64, key: &str) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .with_context(|| format!("invalid configuration value for '{key}'"))
+        .context(ErrorKind::InvalidConfig)
+}
+
+/// List `.toml` drop-ins in a directory, in lexicographic order.
+fn list_dropins(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).with_context(|| format!("failed to read directory {dir:?}")),
+    };
+    let mut dropins = vec![];
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("failed to read directory {dir:?}"))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            dropins.push(path);
+        }
+    }
+    dropins.sort();
+    Ok(dropins)
+}
+
+/// Recursively merge `overlay` on top of `base`.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::load_from(
+            &dir.path().join("afterburn.toml"),
+            &dir.path().join("afterburn.d"),
+        )
+        .unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_load_dropins() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("afterburn.toml");
+        let dropins = dir.path().join("afterburn.d");
+        fs::create_dir(&dropins).unwrap();
+
+        fs::write(
+            &main,
+            r#"
+[outputs]
+attributes = "/run/metadata/afterburn"
+attributes-format = "shell"
+file-mode = 0o640
+file-owner = "0:0"
+hostname = "/etc/hostname"
+kubelet-config = "/run/systemd/system/kubelet.service.d/10-afterburn.conf"
+normalized-attributes = true
+
+[retry]
+max-retries = 3
+
+[providers.aws]
+base-url = "http://127.0.0.1:8080"
+
+[providers.openstack]
+base-url = "https://metadata.example.com"
+ca-bundle = "/etc/pki/afterburn/ca.pem"
+spki-pins = ["sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="]
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dropins.join("20-hostname.toml"),
+            r#"
+[outputs]
+hostname = "/run/afterburn/hostname"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dropins.join("10-retry.toml"),
+            r#"
+[retry]
+max-retries = 5
+initial-backoff = 0.5
+deadline = 60
+"#,
+        )
+        .unwrap();
+        fs::write(dropins.join("30-ignored.conf"), "not toml").unwrap();
+
+        let config = Config::load_from(&main, &dropins).unwrap();
+        assert_eq!(
+            config.outputs.attributes.as_deref(),
+            Some("/run/metadata/afterburn")
+        );
+        assert_eq!(
+            config.outputs.attributes_format,
+            Some(AttributesFormat::Shell)
+        );
+        assert_eq!(config.outputs.file_mode, Some(0o640));
+        assert!(config.outputs.normalized_attributes);
+        assert_eq!(
+            config.outputs.kubelet_config.as_deref(),
+            Some("/run/systemd/system/kubelet.service.d/10-afterburn.conf")
+        );
+        config.outputs.writer().unwrap();
+        assert_eq!(
+            config.outputs.hostname.as_deref(),
+            Some("/run/afterburn/hostname")
+        );
+        assert_eq!(config.retry.max_retries, Some(5));
+        assert_eq!(config.retry.initial_backoff, Some(0.5));
+        assert_eq!(config.retry.deadline, Some(60.0));
+        assert_eq!(
+            config.providers["aws"].base_url.as_deref(),
+            Some("http://127.0.0.1:8080")
+        );
+        let openstack = &config.providers["openstack"];
+        assert_eq!(
+            openstack.ca_bundle.as_deref(),
+            Some(Path::new("/etc/pki/afterburn/ca.pem"))
+        );
+        assert_eq!(openstack.spki_pins.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("afterburn.toml");
+        fs::write(&main, "[outputs]\nunknown-key = true\n").unwrap();
+        Config::load_from(&main, &dir.path().join("afterburn.d")).unwrap_err();
+    }
+
+    #[test]
+    fn test_outputs_writer() {
+        for outputs in [
+            OutputsConfig {
+                file_mode: Some(0o10000),
+                ..Default::default()
+            },
+            OutputsConfig {
+                file_owner: Some("no-such-user-afterburn".into()),
+                ..Default::default()
+            },
+        ] {
+            let err = outputs.writer().unwrap_err();
+            assert_eq!(ErrorKind::of(&err), Some(ErrorKind::InvalidConfig));
+        }
+    }
+
+    #[test]
+    fn test_filter_ssh_keys() {
+        let ed25519 = PublicKey::parse(
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f test",
+        )
+        .unwrap();
+        let keys = vec![ed25519.clone()];
+
+        let policy = SshKeysConfig::default();
+        assert_eq!(policy.filter_keys(keys.clone()).len(), 1);
+
+        let policy = SshKeysConfig {
+            allowed_types: Some(vec!["ssh-ed25519".to_string()]),
+        };
+        assert_eq!(policy.filter_keys(keys.clone()).len(), 1);
+
+        let policy = SshKeysConfig {
+            allowed_types: Some(vec!["ecdsa-sha2-nistp256".to_string()]),
+        };
+        assert_eq!(policy.filter_keys(keys).len(), 0);
+    }
+
+    #[test]
+    fn test_attributes_policy() {
+        let attrs = maplit::hashmap! {
+            "AWS_IPV4_LOCAL".to_string() => "10.0.0.1".to_string(),
+            "AWS_IPV4_PUBLIC".to_string() => "192.0.2.1".to_string(),
+            "AWS_PUBLIC_HOSTNAME".to_string() => "ec2-192-0-2-1".to_string(),
+            "AWS_REGION".to_string() => "us-east-1".to_string(),
+        };
+
+        let policy = AttributesConfig::default();
+        assert_eq!(policy.filter(attrs.clone()), attrs);
+        assert_eq!(policy.prefix().unwrap(), "AFTERBURN_");
+
+        let policy: AttributesConfig = toml::from_str(
+            r#"
+include = ["AWS_*"]
+exclude = ["*PUBLIC*"]
+prefix = "EC2_"
+"#,
+        )
+        .unwrap();
+        let mut kept: Vec<_> = policy.filter(attrs.clone()).into_keys().collect();
+        kept.sort();
+        assert_eq!(kept, ["AWS_IPV4_LOCAL", "AWS_REGION"]);
+        assert_eq!(policy.prefix().unwrap(), "EC2_");
+
+        let policy = AttributesConfig {
+            include: Some(vec!["*_REGION".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(policy.filter(attrs).len(), 1);
+
+        for prefix in ["1CLOUD_", "MY-CLOUD_", "A B"] {
+            let policy = AttributesConfig {
+                prefix: Some(prefix.to_string()),
+                ..Default::default()
+            };
+            let err = policy.prefix().unwrap_err();
+            assert_eq!(ErrorKind::of(&err), Some(ErrorKind::InvalidConfig));
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Dry-run reporting.
+//!
+//! In dry-run mode, outputs are rendered exactly as they would be written,
+//! but instead of touching the system they are collected into a plan and
+//! compared against the current content, permissions and owner of their
+//! target files.
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::util::{AtomicWrite, FileOwner};
+
+/// Effect of a planned write on its target file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Change {
+    /// The file does not exist yet, and would be created.
+    New,
+    /// The file exists, and its content would be replaced.
+    Changed,
+    /// The file exists, with the same content.
+    Unchanged,
+    /// The file exists, and would be removed.
+    Removed,
+    /// The file does not exist, and would not be created.
+    Absent,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Change::New => "new",
+            Change::Changed => "changed",
+            Change::Unchanged => "unchanged",
+            Change::Removed => "removed",
+            Change::Absent => "absent",
+        };
+        write!(f, "{label}")
+    }
+}
+
+/// A single file that would be written (or removed).
+#[derive(Clone, Debug)]
+pub(crate) struct PlannedWrite {
+    /// Target path.
+    pub path: PathBuf,
+    /// New content, or `None` if the file would be removed.
+    pub content: Option<String>,
+    /// Permissions the file would be written with.
+    pub mode: u32,
+    /// Owner the file would be written with, if not the current user.
+    pub owner: Option<FileOwner>,
+    /// Current content, or `None` if the file does not exist.
+    pub current: Option<String>,
+    /// Current permissions and owner, if the file exists.
+    pub current_attrs: Option<(u32, FileOwner)>,
+}
+
+impl PlannedWrite {
+    /// Compare new and current content, permissions and owner.
+    pub(crate) fn change(&self) -> Change {
+        match (&self.current, &self.content) {
+            (None, Some(_)) => Change::New,
+            (None, None) => Change::Absent,
+            (Some(_), None) => Change::Removed,
+            (Some(cur), Some(new)) if cur == new && !self.attrs_changed() => Change::Unchanged,
+            (Some(_), Some(_)) => Change::Changed,
+        }
+    }
+
+    /// Whether the permissions or owner of the existing file would change.
+    fn attrs_changed(&self) -> bool {
+        self.current_attrs.is_some_and(|(mode, owner)| {
+            mode != self.mode || self.owner.is_some_and(|o| o != owner)
+        })
+    }
+}
+
+/// All writes and actions that would be performed.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Plan {
+    pub writes: Vec<PlannedWrite>,
+    /// Whether a boot check-in would be performed.
+    pub check_in: bool,
+}
+
+impl Plan {
+    /// Record that `path` would be written with `content` by `writer`, or
+    /// removed if `None`.
+    pub(crate) fn add(
+        &mut self,
+        path: impl AsRef<Path>,
+        content: Option<String>,
+        writer: &AtomicWrite,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let current = match fs::read_to_string(path) {
+            Ok(c) => Some(c),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("failed to read file {path:?}")),
+        };
+        let current_attrs = match current {
+            Some(_) => {
+                let metadata =
+                    fs::metadata(path).with_context(|| format!("failed to stat file {path:?}"))?;
+                let owner = FileOwner {
+                    uid: metadata.uid(),
+                    gid: metadata.gid(),
+                };
+                Some((metadata.mode() & 0o7777, owner))
+            }
+            None => None,
+        };
+        self.writes.push(PlannedWrite {
+            path: path.to_path_buf(),
+            content,
+            mode: writer.file_mode(current_attrs.map(|(mode, _)| mode)),
+            owner: writer.file_owner(),
+            current,
+            current_attrs,
+        });
+        Ok(())
+    }
+
+    /// Write a diff-like report of the plan.
+    ///
+    /// Each target is introduced by a header with its path and change kind;
+    /// for changed files, the current content is shown with a `-` prefix and
+    /// the new content with a `+` prefix. Permissions and owner of new files,
+    /// and changes to those of existing files, are shown with a `~` prefix.
+    pub(crate) fn report(&self, w: &mut impl Write) -> Result<()> {
+        for write in &self.writes {
+            let change = write.change();
+            writeln!(w, "[{}] {}", change, write.path.display())?;
+            match (change, write.current_attrs) {
+                (Change::New, _) => {
+                    writeln!(w, "~mode {:04o}", write.mode)?;
+                    if let Some(owner) = write.owner {
+                        writeln!(w, "~owner {owner}")?;
+                    }
+                }
+                (Change::Changed, Some((mode, owner))) => {
+                    if mode != write.mode {
+                        writeln!(w, "~mode {:04o} -> {:04o}", mode, write.mode)?;
+                    }
+                    if let Some(new_owner) = write.owner.filter(|o| *o != owner) {
+                        writeln!(w, "~owner {owner} -> {new_owner}")?;
+                    }
+                }
+                _ => {}
+            }
+            let content_changed = write.current != write.content;
+            if matches!(change, Change::Changed | Change::Removed) && content_changed {
+                for line in write.current.iter().flat_map(|c| c.lines()) {
+                    writeln!(w, "-{line}")?;
+                }
+            }
+            if matches!(change, Change::New | Change::Changed) && content_changed {
+                for line in write.content.iter().flat_map(|c| c.lines()) {
+                    writeln!(w, "+{line}")?;
+                }
+            }
+        }
+        if self.check_in {
+            writeln!(w, "[skipped] boot check-in")?;
+        }
+        w.flush().context("failed to write dry-run report")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_plan_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let changed = dir.path().join("changed");
+        let unchanged = dir.path().join("unchanged");
+        let chmod = dir.path().join("chmod");
+        let removed = dir.path().join("removed");
+        for (path, content) in [
+            (&changed, "old\n"),
+            (&unchanged, "same\n"),
+            (&chmod, "same\n"),
+            (&removed, "gone\n"),
+        ] {
+            fs::write(path, content).unwrap();
+            fs::set_permissions(path, Permissions::from_mode(0o644)).unwrap();
+        }
+        let uid = nix::unistd::getuid().as_raw();
+        let gid = nix::unistd::getgid().as_raw();
+        let owner = FileOwner { uid, gid };
+
+        let writer = AtomicWrite::default();
+        let private = AtomicWrite::default().mode(0o600).owner(Some(owner));
+        let mut plan = Plan::default();
+        plan.add(dir.path().join("new"), Some("a\nb\n".into()), &writer)
+            .unwrap();
+        plan.add(dir.path().join("private"), Some("c\n".into()), &private)
+            .unwrap();
+        plan.add(&changed, Some("new\n".into()), &writer).unwrap();
+        plan.add(&unchanged, Some("same\n".into()), &writer)
+            .unwrap();
+        plan.add(&chmod, Some("same\n".into()), &private).unwrap();
+        plan.add(&removed, None, &writer).unwrap();
+        plan.add(dir.path().join("absent"), None, &writer).unwrap();
+        plan.check_in = true;
+
+        let changes: Vec<_> = plan.writes.iter().map(PlannedWrite::change).collect();
+        assert_eq!(
+            changes,
+            vec![
+                Change::New,
+                Change::New,
+                Change::Changed,
+                Change::Unchanged,
+                Change::Changed,
+                Change::Removed,
+                Change::Absent
+            ]
+        );
+
+        let mut out = vec![];
+        plan.report(&mut out).unwrap();
+        let d = dir.path().display();
+        let expected = format!(
+            "[new] {d}/new\n~mode 0644\n+a\n+b\n\
+             [new] {d}/private\n~mode 0600\n~owner {uid}:{gid}\n+c\n\
+             [changed] {d}/changed\n-old\n+new\n\
+             [unchanged] {d}/unchanged\n\
+             [changed] {d}/chmod\n~mode 0644 -> 0600\n\
+             [removed] {d}/removed\n-gone\n\
+             [absent] {d}/absent\n\
+             [skipped] boot check-in\n"
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        // nothing was written
+        assert!(!dir.path().join("new").exists());
+        assert_eq!(fs::read_to_string(&changed).unwrap(), "old\n");
+        assert_eq!(fs::metadata(&chmod).unwrap().mode() & 0o7777, 0o644);
+        assert!(removed.exists());
+    }
+}
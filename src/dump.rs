@@ -0,0 +1,130 @@
+//! Metadata dumping.
+//!
+//! This queries every facet exposed by a metadata provider and collects
+//! the results into a single serializable document. Errors are recorded
+//! per-facet, so that a single failing endpoint does not hide everything else.
+//! Attributes are filtered according to policy, and values of sensitive
+//! ones are masked.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::config::AttributesConfig;
+use crate::network;
+use crate::providers::MetadataProvider;
+use crate::redact;
+
+/// Outcome of querying a single metadata facet.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Facet<T> {
+    /// Facet value, as returned by the provider.
+    Value(T),
+    /// Error message, if the provider failed to return this facet.
+    Error(String),
+}
+
+impl<T> Facet<T> {
+    fn from_result<U>(res: Result<U>, f: impl FnOnce(U) -> T) -> Self {
+        match res {
+            Ok(v) => Facet::Value(f(v)),
+            Err(e) => Facet::Error(format!("{e:#}")),
+        }
+    }
+}
+
+/// All metadata facets for a provider.
+#[derive(Debug, Serialize)]
+pub(crate) struct MetadataDump {
+    pub provider: String,
+    pub attributes: Facet<BTreeMap<String, String>>,
+    pub hostname: Facet<Option<String>>,
+    pub ssh_keys: Facet<Vec<String>>,
+    pub networks: Facet<Vec<network::Interface>>,
+    pub virtual_network_devices: Facet<Vec<network::VirtualNetDev>>,
+    pub netplan_config: Facet<Option<String>>,
+    pub rd_network_kargs: Facet<Option<String>>,
+}
+
+impl MetadataDump {
+    /// Query all facets from the given provider.
+    pub(crate) fn collect(
+        provider_name: &str,
+        provider: &dyn MetadataProvider,
+        policy: &AttributesConfig,
+    ) -> Self {
+        Self {
+            provider: provider_name.to_string(),
+            attributes: Facet::from_result(provider.attributes(), |attrs| {
+                let mut attrs = policy.filter(attrs).into_iter().collect();
+                redact::attributes(&mut attrs);
+                attrs
+            }),
+            hostname: Facet::from_result(provider.hostname(), |v| v),
+            ssh_keys: Facet::from_result(provider.ssh_keys(), |keys| {
+                keys.iter().map(ToString::to_string).collect()
+            }),
+            networks: Facet::from_result(provider.networks(), |v| v),
+            virtual_network_devices: Facet::from_result(provider.virtual_network_devices(), |v| v),
+            netplan_config: Facet::from_result(provider.netplan_config(), |v| v),
+            rd_network_kargs: Facet::from_result(provider.rd_network_kargs(), |v| v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::collections::HashMap;
+
+    struct DumpMock;
+
+    impl MetadataProvider for DumpMock {
+        fn attributes(&self) -> Result<HashMap<String, String>> {
+            Ok(maplit::hashmap! {
+                "MOCK_B".to_string() => "b".to_string(),
+                "MOCK_A".to_string() => "a".to_string(),
+                "MOCK_TOKEN".to_string() => "s3cr3t".to_string(),
+            })
+        }
+
+        fn hostname(&self) -> Result<Option<String>> {
+            bail!("hostname endpoint unreachable")
+        }
+    }
+
+    #[test]
+    fn test_dump_facets() {
+        let dump = MetadataDump::collect("mock", &DumpMock, &AttributesConfig::default());
+        let json = serde_json::to_value(&dump).unwrap();
+
+        assert_eq!(json["provider"], "mock");
+        assert_eq!(
+            json["attributes"],
+            serde_json::json!({"value": {"MOCK_A": "a", "MOCK_B": "b", "MOCK_TOKEN": "<redacted>"}})
+        );
+        assert_eq!(
+            json["hostname"],
+            serde_json::json!({"error": "hostname endpoint unreachable"})
+        );
+        assert_eq!(json["networks"], serde_json::json!({"value": []}));
+        assert_eq!(json["netplan_config"], serde_json::json!({"value": null}));
+
+        // Attribute keys are sorted, so output is stable across runs.
+        let text = serde_json::to_string(&dump).unwrap();
+        assert!(text.find("MOCK_A").unwrap() < text.find("MOCK_B").unwrap());
+
+        // Attributes are filtered by policy.
+        let policy = AttributesConfig {
+            exclude: vec!["*_B".to_string()],
+            ..Default::default()
+        };
+        let dump = MetadataDump::collect("mock", &DumpMock, &policy);
+        assert_eq!(
+            serde_json::to_value(&dump).unwrap()["attributes"],
+            serde_json::json!({"value": {"MOCK_A": "a", "MOCK_TOKEN": "<redacted>"}})
+        );
+    }
+}
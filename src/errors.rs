@@ -0,0 +1,98 @@
+//! Error classification and process exit codes.
+//!
+//! Errors are propagated as `anyhow::Error`. At relevant failure points, an
+//! `ErrorKind` is attached as context, so that `main` can map the failure
+//! to a documented exit code. Kinds are meant to be attached once, as close
+//! as possible to the root cause; if several are present in an error chain,
+//! the outermost one wins.
+
+use std::fmt;
+
+/// Exit code for unclassified failures.
+pub(crate) const EXIT_FAILURE: u8 = 1;
+
+/// Class of a failure, used to select the process exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ErrorKind {
+    /// Invalid command-line arguments or configuration.
+    InvalidConfig,
+    /// Provider not supported.
+    UnsupportedProvider,
+    /// Metadata service unreachable, or returning errors.
+    MetadataUnreachable,
+    /// Config drive not found.
+    ConfigDriveNotFound,
+    /// Metadata could not be parsed.
+    MalformedMetadata,
+    /// Writing to the filesystem failed.
+    WriteFailed,
+}
+
+impl ErrorKind {
+    /// Return the process exit code for this kind of failure.
+    pub(crate) fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::InvalidConfig => 2,
+            ErrorKind::UnsupportedProvider => 3,
+            ErrorKind::MetadataUnreachable => 4,
+            ErrorKind::ConfigDriveNotFound => 5,
+            ErrorKind::MalformedMetadata => 6,
+            ErrorKind::WriteFailed => 7,
+        }
+    }
+
+    /// Return the kind attached to an error, if any.
+    pub(crate) fn of(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<ErrorKind>().copied()
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            ErrorKind::InvalidConfig => "invalid configuration",
+            ErrorKind::UnsupportedProvider => "provider not supported",
+            ErrorKind::MetadataUnreachable => "metadata service unreachable",
+            ErrorKind::ConfigDriveNotFound => "config drive not found",
+            ErrorKind::MalformedMetadata => "malformed metadata",
+            ErrorKind::WriteFailed => "filesystem write failed",
+        };
+        write!(f, "{msg}")
+    }
+}
+
+impl std::error::Error for ErrorKind {}
+
+/// Return the process exit code for an error.
+pub(crate) fn exit_code(err: &anyhow::Error) -> u8 {
+    ErrorKind::of(err).map_or(EXIT_FAILURE, ErrorKind::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_exit_code() {
+        let err = anyhow!("boom");
+        assert_eq!(exit_code(&err), EXIT_FAILURE);
+
+        let err = anyhow!("connection refused")
+            .context(ErrorKind::MetadataUnreachable)
+            .context("failed to run");
+        assert_eq!(exit_code(&err), 4);
+
+        // outermost kind wins
+        let err = anyhow!("connection refused")
+            .context(ErrorKind::MetadataUnreachable)
+            .context("writing hostname")
+            .context(ErrorKind::WriteFailed);
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::WriteFailed));
+
+        let err = Err::<(), _>(anyhow!("permission denied"))
+            .context(ErrorKind::WriteFailed)
+            .unwrap_err();
+        assert_eq!(exit_code(&err), 7);
+    }
+}
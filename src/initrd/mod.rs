@@ -4,12 +4,13 @@
 //! services are configured, so it may not be able to use all usual metadata
 //! fetcher.
 
+use crate::errors::ErrorKind;
 use crate::providers::proxmoxve::ProxmoxVEConfigDrive;
 use crate::providers::vmware::VmwareProvider;
 use crate::providers::MetadataProvider;
+use crate::util::AtomicWrite;
 use anyhow::{Context, Result};
-use std::fs::File;
-use std::io::Write;
+use std::path::Path;
 
 /// Path to cmdline.d fragment for network kernel arguments.
 static KARGS_PATH: &str = "/etc/cmdline.d/50-afterburn-network-kargs.conf";
@@ -25,15 +26,9 @@ pub(crate) fn fetch_network_kargs(provider: &str) -> Result<Option<String>> {
 
 /// Write network kargs into a cmdline.d fragment.
 pub(crate) fn write_network_kargs(kargs: &str) -> Result<()> {
-    let mut fragment_file = File::create(KARGS_PATH)
-        .with_context(|| format!("failed to create file {KARGS_PATH:?}"))?;
-
-    fragment_file
-        .write_all(kargs.as_bytes())
-        .context("failed to write network arguments fragment")?;
-    fragment_file
-        .write_all(b"\n")
-        .context("failed to write trailing newline")?;
-
+    AtomicWrite::default()
+        .write(Path::new(KARGS_PATH), format!("{kargs}\n").as_bytes())
+        .context("failed to write network arguments fragment")
+        .context(ErrorKind::WriteFailed)?;
     Ok(())
 }
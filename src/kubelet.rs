@@ -0,0 +1,328 @@
+//! Kubelet node identity.
+//!
+//! Cloud-controller-managers match Kubernetes nodes to cloud instances by
+//! their provider ID, and label them with the instance region and zone.
+//! Setting both from the kubelet lets nodes register with the identity the
+//! controller expects, without scripting provider-specific attributes.
+//!
+//! Node labels cannot be set in a `KubeletConfiguration` file, so both are
+//! written as environment variables in a systemd drop-in for the kubelet
+//! unit, to be referenced from its command line.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::errors::ErrorKind;
+
+/// Node label for the region.
+const REGION_LABEL: &str = "topology.kubernetes.io/region";
+/// Node label for the zone.
+const ZONE_LABEL: &str = "topology.kubernetes.io/zone";
+
+/// Identity of a node, in the format expected by the cloud-controller-manager
+/// of its provider.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct NodeIdentity {
+    pub provider_id: Option<String>,
+    pub region: Option<String>,
+    pub zone: Option<String>,
+}
+
+impl NodeIdentity {
+    /// Build the identity of a node on `platform` from its attributes.
+    ///
+    /// Parts whose attributes are missing are left unset.
+    pub(crate) fn from_attributes(
+        platform: &str,
+        attributes: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let get = |key: &str| {
+            attributes
+                .get(key)
+                .map(String::as_str)
+                .filter(|v| !v.is_empty())
+        };
+        let identity = match platform {
+            // aws:///<zone>/<instance-id>
+            "aws" => {
+                let zone = get("AWS_AVAILABILITY_ZONE");
+                NodeIdentity {
+                    provider_id: zone
+                        .zip(get("AWS_INSTANCE_ID"))
+                        .map(|(zone, id)| format!("aws:///{zone}/{id}")),
+                    region: get("AWS_REGION").map(String::from),
+                    zone: zone.map(String::from),
+                }
+            }
+            // azure:///subscriptions/<id>/resourceGroups/<group>/providers/Microsoft.Compute/<vm>,
+            // with the resource group in lowercase
+            "azure" => {
+                let location = get("AZURE_LOCATION").map(str::to_ascii_lowercase);
+                // VMs of uniform scale sets are named <vmss>_<instance-id>,
+                // and identified within their scale set; others, including
+                // VMs of flexible scale sets, by their name
+                let vm = get("AZURE_VM_NAME").map(|name| {
+                    let instance = get("AZURE_VMSS_NAME").and_then(|vmss| {
+                        let id = name.strip_prefix(vmss)?.strip_prefix('_')?;
+                        Some((vmss, id))
+                    });
+                    match instance {
+                        Some((vmss, id)) => {
+                            format!("virtualMachineScaleSets/{vmss}/virtualMachines/{id}")
+                        }
+                        None => format!("virtualMachines/{name}"),
+                    }
+                });
+                NodeIdentity {
+                    provider_id: get("AZURE_SUBSCRIPTION_ID")
+                        .zip(get("AZURE_RESOURCE_GROUP"))
+                        .zip(vm)
+                        .map(|((subscription, group), vm)| {
+                            format!(
+                                "azure:///subscriptions/{}/resourceGroups/{}/providers/Microsoft.Compute/{}",
+                                subscription,
+                                group.to_ascii_lowercase(),
+                                vm
+                            )
+                        }),
+                    // availability zones are numbered within the location
+                    zone: location
+                        .as_deref()
+                        .zip(get("AZURE_ZONE"))
+                        .map(|(location, zone)| format!("{location}-{zone}")),
+                    region: location,
+                }
+            }
+            // gce://<project>/<zone>/<name>
+            "gcp" => {
+                let zone = get("GCP_ZONE");
+                NodeIdentity {
+                    provider_id: get("GCP_PROJECT_ID")
+                        .zip(zone)
+                        .zip(get("GCP_INSTANCE_NAME"))
+                        .map(|((project, zone), name)| format!("gce://{project}/{zone}/{name}")),
+                    // zones are named <region>-<letter>
+                    region: zone
+                        .and_then(|z| z.rsplit_once('-'))
+                        .map(|(region, _)| region.to_string()),
+                    zone: zone.map(String::from),
+                }
+            }
+            // hcloud://<server-id>
+            "hetzner" => {
+                // the zone is the datacenter, e.g. fsn1-dc14, and the region
+                // its location
+                let zone = get("HETZNER_AVAILABILITY_ZONE");
+                NodeIdentity {
+                    provider_id: get("HETZNER_INSTANCE_ID").map(|id| format!("hcloud://{id}")),
+                    region: zone
+                        .and_then(|z| z.split_once('-'))
+                        .map(|(location, _)| location.to_string()),
+                    zone: zone.map(String::from),
+                }
+            }
+            // openstack:///<instance-uuid>; the region is only known to the
+            // controller configuration
+            "openstack" | "openstack-metadata" => NodeIdentity {
+                provider_id: get("OPENSTACK_INSTANCE_UUID").map(|id| format!("openstack:///{id}")),
+                region: None,
+                zone: get("OPENSTACK_AVAILABILITY_ZONE").map(String::from),
+            },
+            _ => {
+                return Err(anyhow!(
+                    "kubelet configuration is not supported on provider '{}'",
+                    platform
+                )
+                .context(ErrorKind::UnsupportedProvider))
+            }
+        };
+        Ok(identity)
+    }
+
+    /// Render a systemd drop-in for the kubelet unit.
+    ///
+    /// Both variables are always set, possibly empty, so that the unit can
+    /// reference them unconditionally.
+    pub(crate) fn render(&self) -> String {
+        let labels: Vec<String> = [(REGION_LABEL, &self.region), (ZONE_LABEL, &self.zone)]
+            .into_iter()
+            .filter_map(|(label, value)| Some(format!("{}={}", label, value.as_ref()?)))
+            .collect();
+        format!(
+            "[Service]\nEnvironment={}\nEnvironment={}\n",
+            quote(&format!(
+                "KUBELET_PROVIDER_ID={}",
+                self.provider_id.as_deref().unwrap_or_default()
+            )),
+            quote(&format!("KUBELET_NODE_LABELS={}", labels.join(","))),
+        )
+    }
+}
+
+/// Quote an assignment for a systemd `Environment=` setting.
+///
+/// Within double quotes, systemd unescapes C-style escapes; `%` starts a
+/// specifier everywhere and is doubled.
+fn quote(assignment: &str) -> String {
+    let mut quoted = String::with_capacity(assignment.len() + 2);
+    quoted.push('"');
+    for c in assignment.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '%' => quoted.push_str("%%"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{self, Capability};
+
+    fn attributes(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_attributes() {
+        let cases = [
+            (
+                "aws",
+                attributes(&[
+                    ("AWS_AVAILABILITY_ZONE", "us-east-1a"),
+                    ("AWS_INSTANCE_ID", "i-0123456789"),
+                    ("AWS_REGION", "us-east-1"),
+                ]),
+                ("aws:///us-east-1a/i-0123456789", "us-east-1", "us-east-1a"),
+            ),
+            (
+                "azure",
+                attributes(&[
+                    ("AZURE_LOCATION", "westeurope"),
+                    ("AZURE_RESOURCE_GROUP", "MyGroup"),
+                    ("AZURE_SUBSCRIPTION_ID", "sub-1"),
+                    ("AZURE_VM_NAME", "node-0"),
+                    ("AZURE_ZONE", "2"),
+                ]),
+                (
+                    "azure:///subscriptions/sub-1/resourceGroups/mygroup/providers/Microsoft.Compute/virtualMachines/node-0",
+                    "westeurope",
+                    "westeurope-2",
+                ),
+            ),
+            (
+                "azure",
+                attributes(&[
+                    ("AZURE_LOCATION", "westeurope"),
+                    ("AZURE_RESOURCE_GROUP", "MyGroup"),
+                    ("AZURE_SUBSCRIPTION_ID", "sub-1"),
+                    ("AZURE_VM_NAME", "pool_3"),
+                    ("AZURE_VMSS_NAME", "pool"),
+                    ("AZURE_ZONE", "1"),
+                ]),
+                (
+                    "azure:///subscriptions/sub-1/resourceGroups/mygroup/providers/Microsoft.Compute/virtualMachineScaleSets/pool/virtualMachines/3",
+                    "westeurope",
+                    "westeurope-1",
+                ),
+            ),
+            (
+                "gcp",
+                attributes(&[
+                    ("GCP_INSTANCE_NAME", "node-0"),
+                    ("GCP_PROJECT_ID", "my-project"),
+                    ("GCP_ZONE", "us-central1-a"),
+                ]),
+                ("gce://my-project/us-central1-a/node-0", "us-central1", "us-central1-a"),
+            ),
+            (
+                "hetzner",
+                attributes(&[
+                    ("HETZNER_AVAILABILITY_ZONE", "fsn1-dc14"),
+                    ("HETZNER_INSTANCE_ID", "12345"),
+                    ("HETZNER_REGION", "eu-central"),
+                ]),
+                ("hcloud://12345", "fsn1", "fsn1-dc14"),
+            ),
+        ];
+        for (platform, attrs, (provider_id, region, zone)) in cases {
+            assert_eq!(
+                NodeIdentity::from_attributes(platform, &attrs).unwrap(),
+                NodeIdentity {
+                    provider_id: Some(provider_id.into()),
+                    region: Some(region.into()),
+                    zone: Some(zone.into()),
+                },
+                "{platform}"
+            );
+        }
+
+        // missing attributes leave parts unset
+        let identity = NodeIdentity::from_attributes(
+            "openstack",
+            &attributes(&[("OPENSTACK_INSTANCE_UUID", "99dcf33b")]),
+        )
+        .unwrap();
+        assert_eq!(
+            identity,
+            NodeIdentity {
+                provider_id: Some("openstack:///99dcf33b".into()),
+                ..Default::default()
+            }
+        );
+
+        let err = NodeIdentity::from_attributes("vultr", &HashMap::new()).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::UnsupportedProvider));
+
+        // supported providers match the registry
+        for info in metadata::PROVIDERS {
+            assert_eq!(
+                NodeIdentity::from_attributes(info.id, &HashMap::new()).is_ok(),
+                info.supports(Capability::KubeletConfig),
+                "{}",
+                info.id
+            );
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let identity = NodeIdentity {
+            provider_id: Some("aws:///us-east-1a/i-0123456789".into()),
+            region: Some("us-east-1".into()),
+            zone: Some("us-east-1a".into()),
+        };
+        assert_eq!(
+            identity.render(),
+            concat!(
+                "[Service]\n",
+                "Environment=\"KUBELET_PROVIDER_ID=aws:///us-east-1a/i-0123456789\"\n",
+                "Environment=\"KUBELET_NODE_LABELS=topology.kubernetes.io/region=us-east-1,topology.kubernetes.io/zone=us-east-1a\"\n",
+            )
+        );
+
+        let identity = NodeIdentity {
+            zone: Some("100%\"zone\"".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            identity.render(),
+            concat!(
+                "[Service]\n",
+                "Environment=\"KUBELET_PROVIDER_ID=\"\n",
+                "Environment=\"KUBELET_NODE_LABELS=topology.kubernetes.io/zone=100%%\\\"zone\\\"\"\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Logging setup.
+//!
+//! Log records can be emitted in human-readable form on the terminal, as
+//! JSON lines, or as native journal entries. In the latter cases, record
+//! key-values (e.g. `provider`, `url` or `attempt`) are kept as structured
+//! fields, so that they can be filtered without parsing messages.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use clap::ValueEnum;
+use libsystemd::logging::{self, Priority};
+use slog::{slog_o, Drain, Key, Level, OwnedKVList, Record, KV};
+
+/// Prefix for journal fields carrying record key-values.
+const JOURNAL_FIELD_PREFIX: &str = "AFTERBURN_";
+
+/// Output format for log records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum LogFormat {
+    /// Human-readable terminal output
+    #[default]
+    Term,
+    /// JSON lines on standard error
+    Json,
+    /// Native systemd journal entries
+    Journal,
+}
+
+/// Setup the global logger, returning a guard which must be kept alive.
+pub(crate) fn init(format: LogFormat) -> slog_scope::GlobalLoggerGuard {
+    let log = match format {
+        LogFormat::Term => {
+            let decorator = slog_term::TermDecorator::new().build();
+            let drain = slog_term::FullFormat::new(decorator).build().fuse();
+            let drain = slog_async::Async::new(drain).build().fuse();
+            slog::Logger::root(drain, slog_o!())
+        }
+        LogFormat::Json => {
+            let drain = slog_json::Json::default(std::io::stderr()).fuse();
+            let drain = slog_async::Async::new(drain).build().fuse();
+            slog::Logger::root(drain, slog_o!())
+        }
+        LogFormat::Journal => {
+            let drain = slog_async::Async::new(JournalDrain).build().fuse();
+            slog::Logger::root(drain, slog_o!())
+        }
+    };
+    slog_scope::set_global_logger(log)
+}
+
+/// Drain sending records to the systemd journal.
+struct JournalDrain;
+
+impl Drain for JournalDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), slog::Never> {
+        let mut fields = JournalFields::default();
+        // Errors while collecting fields are not fatal; whatever was
+        // collected is still sent.
+        values.serialize(record, &mut fields).ok();
+        record.kv().serialize(record, &mut fields).ok();
+        fields
+            .0
+            .insert("CODE_MODULE".into(), record.module().into());
+        fields.0.insert("CODE_FILE".into(), record.file().into());
+        fields
+            .0
+            .insert("SYSLOG_IDENTIFIER".into(), env!("CARGO_PKG_NAME").into());
+        fields
+            .0
+            .insert("CODE_LINE".into(), record.line().to_string());
+
+        let message = record.msg().to_string();
+        if let Err(e) =
+            logging::journal_send(level_to_priority(record.level()), &message, fields.0.iter())
+        {
+            // There is nowhere else to report this; fall back to stderr.
+            eprintln!("failed to send log entry to journald: {e}: {message}");
+        }
+        Ok(())
+    }
+}
+
+/// Map a slog level to a journal priority.
+fn level_to_priority(level: Level) -> Priority {
+    match level {
+        Level::Critical => Priority::Critical,
+        Level::Error => Priority::Error,
+        Level::Warning => Priority::Warning,
+        Level::Info => Priority::Info,
+        Level::Debug | Level::Trace => Priority::Debug,
+    }
+}
+
+/// Convert a record key to a valid journal field name.
+///
+/// Journal field names may only contain uppercase letters, digits and
+/// underscores.
+fn journal_field_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| match c {
+            'a'..='z' => c.to_ascii_uppercase(),
+            'A'..='Z' | '0'..='9' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{JOURNAL_FIELD_PREFIX}{name}")
+}
+
+/// Record key-values, as journal fields.
+#[derive(Debug, Default)]
+struct JournalFields(BTreeMap<String, String>);
+
+impl slog::Serializer for JournalFields {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        self.0.insert(journal_field_name(key), val.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{b, record};
+
+    #[test]
+    fn test_journal_fields() {
+        let mut fields = JournalFields::default();
+        let kv = b!(
+            "provider" => "aws",
+            "url" => "http://169.254.169.254/",
+            "attempt" => 2,
+            "max-retries" => 10
+        );
+        let mut serialize = |rec: &Record| rec.kv().serialize(rec, &mut fields).unwrap();
+        serialize(&record!(Level::Info, "", &format_args!("msg"), kv));
+
+        assert_eq!(fields.0["AFTERBURN_PROVIDER"], "aws");
+        assert_eq!(fields.0["AFTERBURN_URL"], "http://169.254.169.254/");
+        assert_eq!(fields.0["AFTERBURN_ATTEMPT"], "2");
+        assert_eq!(fields.0["AFTERBURN_MAX_RETRIES"], "10");
+    }
+
+    #[test]
+    fn test_level_to_priority() {
+        assert!(matches!(
+            level_to_priority(Level::Warning),
+            Priority::Warning
+        ));
+        assert!(matches!(level_to_priority(Level::Trace), Priority::Debug));
+    }
+}
@@ -12,31 +12,47 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod agent;
+mod attributes;
 mod cli;
+mod config;
+mod dryrun;
+mod dump;
+mod errors;
 mod initrd;
+mod kubelet;
+mod logging;
 mod metadata;
 mod network;
 mod providers;
+mod redact;
 mod retry;
+mod snapshot;
 mod util;
 
 use anyhow::{Context, Result};
-use slog::{slog_o, Drain};
-use slog_scope::debug;
+use slog_scope::{debug, trace};
 use std::env;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    // Setup logging.
-    let decorator = slog_term::TermDecorator::new().build();
-    let drain = slog_term::FullFormat::new(decorator).build().fuse();
-    let drain = slog_async::Async::new(drain).build().fuse();
-    let log = slog::Logger::root(drain, slog_o!());
-    let _guard = slog_scope::set_global_logger(log);
-    debug!("logging initialized");
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(errors::exit_code(&e))
+        }
+    }
+}
 
+fn run() -> Result<()> {
     // Parse command-line arguments.
     let cli_cmd = cli::parse_args(env::args())?;
-    debug!("command-line arguments parsed");
+
+    // Setup logging.
+    let _guard = logging::init(cli_cmd.log_format());
+    debug!("logging initialized");
+    trace!("cli configuration - {:?}", cli_cmd);
 
     // Run core logic.
     cli_cmd.run().context("failed to run")?;
@@ -12,8 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
 
+use crate::attributes::{Normalized, ValueType};
+use crate::errors::ErrorKind;
 use crate::providers;
 use crate::providers::akamai::AkamaiProvider;
 use crate::providers::aliyun::AliyunProvider;
@@ -37,6 +42,10 @@ use crate::providers::proxmoxve;
 use crate::providers::scaleway::ScalewayProvider;
 use crate::providers::vmware::VmwareProvider;
 use crate::providers::vultr::VultrProvider;
+use crate::snapshot::Snapshot;
+
+/// Prefix for replaying metadata from a recorded snapshot directory.
+pub const REPLAY_PREFIX: &str = "file:";
 
 macro_rules! box_result {
     ($exp:expr) => {
@@ -44,37 +53,404 @@ macro_rules! box_result {
     };
 }
 
+macro_rules! caps {
+    ($($cap:ident),*) => {
+        &[$(Capability::$cap),*]
+    };
+}
+
+/// Metadata features a provider may support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    Attributes,
+    Hostname,
+    SshKeys,
+    Networks,
+    NetplanConfig,
+    RdNetworkKargs,
+    BootCheckin,
+    KubeletConfig,
+}
+
+impl Capability {
+    /// All capabilities, in display order.
+    pub const ALL: &'static [Capability] = caps![
+        Attributes,
+        Hostname,
+        SshKeys,
+        Networks,
+        NetplanConfig,
+        RdNetworkKargs,
+        BootCheckin,
+        KubeletConfig
+    ];
+
+    /// Return the capability name, as used in text and JSON output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Attributes => "attributes",
+            Capability::Hostname => "hostname",
+            Capability::SshKeys => "ssh-keys",
+            Capability::Networks => "networks",
+            Capability::NetplanConfig => "netplan-config",
+            Capability::RdNetworkKargs => "rd-network-kargs",
+            Capability::BootCheckin => "boot-checkin",
+            Capability::KubeletConfig => "kubelet-config",
+        }
+    }
+}
+
+/// A registered metadata provider.
+#[derive(Debug, Serialize)]
+pub struct ProviderInfo {
+    /// Provider ID, matching the Ignition platform ID.
+    pub id: &'static str,
+    /// Alternative names accepted for this provider.
+    pub aliases: &'static [&'static str],
+    /// Supported metadata features.
+    pub capabilities: &'static [Capability],
+    /// Attributes mapped to normalized ones.
+    #[serde(skip)]
+    pub normalized: &'static Normalized<&'static str>,
+    /// Types of attribute values other than strings.
+    #[serde(skip)]
+    pub value_types: &'static [(&'static str, ValueType)],
+    /// Constructor fetching metadata from this provider.
+    #[serde(skip)]
+    fetch: fn() -> Result<Box<dyn providers::MetadataProvider>>,
+}
+
+impl ProviderInfo {
+    /// Whether this provider supports the given capability.
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// Registry of all supported providers, sorted by ID.
+pub static PROVIDERS: &[ProviderInfo] = &[
+    ProviderInfo {
+        id: "akamai",
+        aliases: &[],
+        capabilities: providers::akamai::CAPABILITIES,
+        normalized: &providers::akamai::NORMALIZED_KEYS,
+        value_types: providers::akamai::VALUE_TYPES,
+        fetch: || box_result!(AkamaiProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "aliyun",
+        aliases: &[],
+        capabilities: providers::aliyun::CAPABILITIES,
+        normalized: &providers::aliyun::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(AliyunProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "aws",
+        aliases: &[],
+        capabilities: providers::aws::CAPABILITIES,
+        normalized: &providers::aws::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(AwsProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "azure",
+        aliases: &[],
+        capabilities: providers::microsoft::azure::CAPABILITIES,
+        normalized: &providers::microsoft::azure::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(Azure::try_new()?),
+    },
+    ProviderInfo {
+        id: "azurestack",
+        aliases: &[],
+        capabilities: providers::microsoft::azurestack::CAPABILITIES,
+        normalized: &Normalized::NONE,
+        value_types: &[],
+        fetch: || box_result!(AzureStack::try_new()?),
+    },
+    ProviderInfo {
+        id: "cloudstack-configdrive",
+        aliases: &[],
+        capabilities: providers::cloudstack::CAPABILITIES,
+        normalized: &providers::cloudstack::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(ConfigDrive::try_new()?),
+    },
+    ProviderInfo {
+        id: "cloudstack-metadata",
+        aliases: &[],
+        capabilities: providers::cloudstack::CAPABILITIES,
+        normalized: &providers::cloudstack::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(CloudstackNetwork::try_new()?),
+    },
+    ProviderInfo {
+        id: "digitalocean",
+        aliases: &[],
+        capabilities: providers::digitalocean::CAPABILITIES,
+        normalized: &providers::digitalocean::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(DigitalOceanProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "exoscale",
+        aliases: &[],
+        capabilities: providers::exoscale::CAPABILITIES,
+        normalized: &providers::exoscale::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(ExoscaleProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "gcp",
+        aliases: &[],
+        capabilities: providers::gcp::CAPABILITIES,
+        normalized: &providers::gcp::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(GcpProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "hetzner",
+        aliases: &[],
+        capabilities: providers::hetzner::CAPABILITIES,
+        normalized: &providers::hetzner::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(HetznerProvider::try_new()?),
+    },
+    // IBM Cloud - VPC Generation 2.
+    ProviderInfo {
+        id: "ibmcloud",
+        aliases: &[],
+        capabilities: providers::ibmcloud::CAPABILITIES,
+        normalized: &providers::ibmcloud::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(IBMGen2Provider::try_new()?),
+    },
+    // IBM Cloud - Classic infrastructure.
+    ProviderInfo {
+        id: "ibmcloud-classic",
+        aliases: &[],
+        capabilities: providers::ibmcloud_classic::CAPABILITIES,
+        normalized: &providers::ibmcloud_classic::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(IBMClassicProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "kubevirt",
+        aliases: &[],
+        capabilities: providers::kubevirt::CAPABILITIES,
+        normalized: &providers::kubevirt::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(KubeVirtProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "openstack",
+        aliases: &[],
+        capabilities: openstack::CAPABILITIES,
+        normalized: &openstack::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: openstack::try_config_drive_else_network,
+    },
+    ProviderInfo {
+        id: "openstack-metadata",
+        aliases: &[],
+        capabilities: openstack::CAPABILITIES,
+        normalized: &openstack::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(OpenstackProviderNetwork::try_new()?),
+    },
+    ProviderInfo {
+        id: "packet",
+        aliases: &[],
+        capabilities: providers::packet::CAPABILITIES,
+        normalized: &providers::packet::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(PacketProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "powervs",
+        aliases: &[],
+        capabilities: providers::powervs::CAPABILITIES,
+        normalized: &providers::powervs::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(PowerVSProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "proxmoxve",
+        aliases: &[],
+        capabilities: proxmoxve::CAPABILITIES,
+        normalized: &proxmoxve::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: proxmoxve::try_config_drive_else_leave,
+    },
+    ProviderInfo {
+        id: "scaleway",
+        aliases: &[],
+        capabilities: providers::scaleway::CAPABILITIES,
+        normalized: &providers::scaleway::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(ScalewayProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "vmware",
+        aliases: &[],
+        capabilities: providers::vmware::CAPABILITIES,
+        normalized: &Normalized::NONE,
+        value_types: &[],
+        fetch: || box_result!(VmwareProvider::try_new()?),
+    },
+    ProviderInfo {
+        id: "vultr",
+        aliases: &[],
+        capabilities: providers::vultr::CAPABILITIES,
+        normalized: &providers::vultr::NORMALIZED_KEYS,
+        value_types: &[],
+        fetch: || box_result!(VultrProvider::try_new()?),
+    },
+];
+
+/// Look up a provider by ID or alias.
+pub fn lookup_provider(name: &str) -> Option<&'static ProviderInfo> {
+    PROVIDERS
+        .iter()
+        .find(|p| p.id == name || p.aliases.contains(&name))
+}
+
+/// Return the type of the attribute named `key`, if not a string.
+///
+/// Attribute names are prefixed by provider, so types are looked up across
+/// all providers.
+pub fn value_type(key: &str) -> Option<ValueType> {
+    PROVIDERS
+        .iter()
+        .flat_map(|info| info.value_types)
+        .find(|(k, _)| *k == key)
+        .map(|(_, t)| *t)
+}
+
+/// Return the normalized attributes of `platform`, given its attributes.
+///
+/// Unknown platforms only get the `PLATFORM` attribute.
+pub fn normalized_attributes(
+    platform: &str,
+    attributes: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let keys = lookup_provider(platform).map_or(&Normalized::NONE, |info| info.normalized);
+    keys.pick(attributes).into_attributes(platform)
+}
+
 /// Fetch metadata for the given provider.
 ///
 /// This is the generic, top-level function to fetch provider metadata.
 /// The configured provider is passed in and this function dispatches the call
 /// to the provider-specific fetch logic.
 pub fn fetch_metadata(provider: &str) -> Result<Box<dyn providers::MetadataProvider>> {
-    match provider {
-        "akamai" => box_result!(AkamaiProvider::try_new()?),
-        "aliyun" => box_result!(AliyunProvider::try_new()?),
-        "aws" => box_result!(AwsProvider::try_new()?),
-        "azure" => box_result!(Azure::try_new()?),
-        "azurestack" => box_result!(AzureStack::try_new()?),
-        "cloudstack-metadata" => box_result!(CloudstackNetwork::try_new()?),
-        "cloudstack-configdrive" => box_result!(ConfigDrive::try_new()?),
-        "digitalocean" => box_result!(DigitalOceanProvider::try_new()?),
-        "exoscale" => box_result!(ExoscaleProvider::try_new()?),
-        "gcp" => box_result!(GcpProvider::try_new()?),
-        "hetzner" => box_result!(HetznerProvider::try_new()?),
-        // IBM Cloud - VPC Generation 2.
-        "ibmcloud" => box_result!(IBMGen2Provider::try_new()?),
-        // IBM Cloud - Classic infrastructure.
-        "ibmcloud-classic" => box_result!(IBMClassicProvider::try_new()?),
-        "kubevirt" => box_result!(KubeVirtProvider::try_new()?),
-        "openstack" => openstack::try_config_drive_else_network(),
-        "openstack-metadata" => box_result!(OpenstackProviderNetwork::try_new()?),
-        "packet" => box_result!(PacketProvider::try_new()?),
-        "powervs" => box_result!(PowerVSProvider::try_new()?),
-        "proxmoxve" => proxmoxve::try_config_drive_else_leave(),
-        "scaleway" => box_result!(ScalewayProvider::try_new()?),
-        "vmware" => box_result!(VmwareProvider::try_new()?),
-        "vultr" => box_result!(VultrProvider::try_new()?),
-        _ => bail!("unknown provider '{}'", provider),
+    if let Some(dir) = provider.strip_prefix(REPLAY_PREFIX) {
+        let dir = Path::new(dir);
+        let snapshot = Snapshot::read_from(dir)?;
+        slog_scope::info!(
+            "replaying metadata recorded from provider '{}' in {:?}",
+            snapshot.provider,
+            dir
+        );
+        return box_result!(snapshot);
+    }
+
+    match lookup_provider(provider) {
+        Some(info) => (info.fetch)(),
+        None => {
+            Err(anyhow!("unknown provider '{}'", provider).context(ErrorKind::UnsupportedProvider))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kubelet::NodeIdentity;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_registry_names() {
+        let mut seen = HashSet::new();
+        for info in PROVIDERS {
+            for name in std::iter::once(&info.id).chain(info.aliases) {
+                assert!(seen.insert(*name), "duplicate provider name '{name}'");
+                assert!(!name.starts_with(REPLAY_PREFIX));
+            }
+            assert!(!info.capabilities.is_empty(), "{}", info.id);
+        }
+
+        let ids: Vec<_> = PROVIDERS.iter().map(|p| p.id).collect();
+        let mut sorted = ids.clone();
+        sorted.sort_unstable();
+        assert_eq!(ids, sorted);
+    }
+
+    #[test]
+    fn test_lookup_provider() {
+        assert_eq!(lookup_provider("aws").unwrap().id, "aws");
+        assert!(lookup_provider("aws")
+            .unwrap()
+            .supports(Capability::SshKeys));
+        assert!(!lookup_provider("vmware")
+            .unwrap()
+            .supports(Capability::SshKeys));
+        assert!(lookup_provider("unknown").is_none());
+
+        let err = fetch_metadata("unknown").err().unwrap();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::UnsupportedProvider));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        for info in PROVIDERS {
+            // kubelet configuration is derived from attributes
+            let kubelet = NodeIdentity::from_attributes(info.id, &HashMap::new()).is_ok();
+            assert_eq!(
+                info.supports(Capability::KubeletConfig),
+                kubelet,
+                "{}",
+                info.id
+            );
+            if kubelet || *info.normalized != Normalized::NONE {
+                assert!(info.supports(Capability::Attributes), "{}", info.id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_normalized_attributes() {
+        let attrs = maplit::hashmap! {
+            "AWS_AVAILABILITY_ZONE".to_string() => "us-east-1a".to_string(),
+            "AWS_INSTANCE_ID".to_string() => "i-0123456789".to_string(),
+            "AWS_REGION".to_string() => "us-east-1".to_string(),
+        };
+        assert_eq!(
+            normalized_attributes("aws", &attrs),
+            maplit::hashmap! {
+                "PLATFORM".to_string() => "aws".to_string(),
+                "INSTANCE_ID".to_string() => "i-0123456789".to_string(),
+                "REGION".to_string() => "us-east-1".to_string(),
+                "ZONE".to_string() => "us-east-1a".to_string(),
+            }
+        );
+        assert_eq!(
+            normalized_attributes("vmware", &attrs),
+            maplit::hashmap! { "PLATFORM".to_string() => "vmware".to_string() }
+        );
+
+        // every mapped or typed key is a provider attribute
+        for info in PROVIDERS {
+            let prefix = info.id.split('-').next().unwrap().to_ascii_uppercase();
+            let mapped = info.normalized.clone().fields().into_iter();
+            let typed = info.value_types.iter().map(|(key, _)| Some(*key));
+            for key in mapped.map(|(_, key)| key).chain(typed).flatten() {
+                assert!(key.starts_with(&prefix), "{}: {key}", info.id);
+            }
+        }
+        assert_eq!(value_type("AKAMAI_INSTANCE_VCPUS"), Some(ValueType::Number));
+        assert_eq!(value_type("AKAMAI_INSTANCE_ID"), None);
     }
 }
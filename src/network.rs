@@ -19,6 +19,7 @@
 use anyhow::{anyhow, bail, Context, Result};
 use ipnetwork::IpNetwork;
 use pnet_base::MacAddr;
+use serde::{Deserialize, Serialize};
 use std::fmt::Write;
 use std::net::IpAddr;
 use std::string::String;
@@ -57,7 +58,7 @@ pub fn try_parse_cidr(address: IpAddr, netmask: IpAddr) -> Result<IpNetwork> {
     IpNetwork::new(address, prefix).context("failed to parse network")
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NetworkRoute {
     pub destination: IpNetwork,
     pub gateway: IpAddr,
@@ -67,7 +68,7 @@ pub struct NetworkRoute {
 ///
 /// Depending on platforms, an interface may be identified by
 /// name or by MAC address (at least one of those must be provided).
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Interface {
     /// Interface name.
     pub name: Option<String>,
@@ -89,7 +90,7 @@ pub struct Interface {
 }
 
 /// A virtual network interface.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VirtualNetDev {
     pub name: String,
     pub kind: NetDevKind,
@@ -99,7 +100,7 @@ pub struct VirtualNetDev {
 }
 
 /// A free-form `systemd.netdev` section.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SdSection {
     pub name: String,
     pub attributes: Vec<(String, String)>,
@@ -107,7 +108,8 @@ pub struct SdSection {
 
 /// Supported virtual network device kinds.
 #[allow(dead_code)]
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum NetDevKind {
     /// Parent aggregation for physically bonded devices.
     Bond,
@@ -132,7 +134,8 @@ impl NetDevKind {
 
 /// Optional use of DHCP.
 #[allow(dead_code)]
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DhcpSetting {
     Both,
     V4,
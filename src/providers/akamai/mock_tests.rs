@@ -93,12 +93,22 @@ fn test_attributes() {
         "AKAMAI_INSTANCE_REGION".to_string() => "us-ord".to_string(),
         "AKAMAI_INSTANCE_TYPE".to_string() => "g6-nanode-1".to_string(),
         "AKAMAI_INSTANCE_TAGS".to_string() => "foo:bar:baz".to_string(),
+        "AKAMAI_INSTANCE_VCPUS".to_string() => "1".to_string(),
+        "AKAMAI_INSTANCE_MEMORY".to_string() => "1024".to_string(),
+        "AKAMAI_INSTANCE_DISK".to_string() => "25600".to_string(),
+        "AKAMAI_INSTANCE_TRANSFER".to_string() => "1000".to_string(),
+        "AKAMAI_INSTANCE_GPUS".to_string() => "0".to_string(),
+        "AKAMAI_INSTANCE_BACKUPS_ENABLED".to_string() => "false".to_string(),
         "AKAMAI_PUBLIC_IPV4_0".to_string() => "1.2.3.4/32".to_string(),
         "AKAMAI_PRIVATE_IPV4_0".to_string() => "192.168.1.1/32".to_string(),
         "AKAMAI_IPV6_SLAAC".to_string() => "2600:3c06::f03c:94ff:fecb:c10b/128".to_string(),
         "AKAMAI_IPV6_LINK_LOCAL".to_string() => "fe80::f03c:94ff:fecb:c10b/128".to_string(),
     };
     assert_eq!(expected, actual);
+    assert_eq!(
+        super::NORMALIZED_KEYS.missing_keys(&actual),
+        Vec::<&str>::new()
+    );
 
     server.reset();
 }
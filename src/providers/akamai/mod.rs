@@ -25,7 +25,10 @@ use openssh_keys::PublicKey;
 use reqwest::header::{HeaderName, HeaderValue};
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::time::Duration;
 
+use crate::attributes::{Normalized, ValueType};
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
@@ -39,24 +42,8 @@ pub struct AkamaiProvider {
 impl AkamaiProvider {
     /// Instantiate a new `AkamaiProvider`.
     pub fn try_new() -> Result<Self> {
-        // Get a metadata token.
         let client = retry::Client::try_new()?;
-        let token = get_token(client)?;
-
-        // Create the new client with the token pre-loaded into a header.
-        // All of the other endpoints accept "text/plain" and "application/json".
-        // Let's prefer JSON.
-        let client = retry::Client::try_new()?
-            .header(
-                HeaderName::from_static("metadata-token"),
-                HeaderValue::from_str(&token)?,
-            )
-            .header(
-                HeaderName::from_static("accept"),
-                HeaderValue::from_static("application/json"),
-            )
-            .return_on_404(true);
-        Ok(Self { client })
+        Ok(Self::with_client(client))
     }
 
     /// Instantiate a new `AkamaiProvider` with a specific client.
@@ -64,25 +51,21 @@ impl AkamaiProvider {
     /// NOTE: This method solely exists for testing.
     #[cfg(test)]
     pub fn with_base_url(url: String) -> Result<Self> {
-        let client = retry::Client::try_new()?
-            .mock_base_url(url.clone())
-            .return_on_404(true)
-            .max_retries(0);
-        let token = get_token(client)?;
+        let client = retry::Client::try_new()?.mock_base_url(url).max_retries(0);
+        Ok(Self::with_client(client))
+    }
 
-        let client = retry::Client::try_new()?
-            .header(
-                HeaderName::from_static("metadata-token"),
-                HeaderValue::from_str(&token)?,
-            )
+    fn with_client(client: retry::Client) -> Self {
+        // All of the other endpoints accept "text/plain" and "application/json".
+        // Let's prefer JSON.
+        let client = client
+            .token_source(AkamaiTokenSource)
             .header(
                 HeaderName::from_static("accept"),
                 HeaderValue::from_static("application/json"),
             )
-            .mock_base_url(url)
-            .return_on_404(true)
-            .max_retries(0);
-        Ok(Self { client })
+            .return_on_404(true);
+        Self { client }
     }
 
     fn endpoint_for(key: &str) -> String {
@@ -135,6 +118,30 @@ impl AkamaiProvider {
             ("AKAMAI_INSTANCE_REGION".to_string(), data.region.clone()),
             ("AKAMAI_INSTANCE_TYPE".to_string(), data.r#type.clone()),
             ("AKAMAI_INSTANCE_TAGS".to_string(), data.tags.join(":")),
+            (
+                "AKAMAI_INSTANCE_VCPUS".to_string(),
+                data.specs.vcpus.to_string(),
+            ),
+            (
+                "AKAMAI_INSTANCE_MEMORY".to_string(),
+                data.specs.memory.to_string(),
+            ),
+            (
+                "AKAMAI_INSTANCE_DISK".to_string(),
+                data.specs.disk.to_string(),
+            ),
+            (
+                "AKAMAI_INSTANCE_TRANSFER".to_string(),
+                data.specs.transfer.to_string(),
+            ),
+            (
+                "AKAMAI_INSTANCE_GPUS".to_string(),
+                data.specs.gpus.to_string(),
+            ),
+            (
+                "AKAMAI_INSTANCE_BACKUPS_ENABLED".to_string(),
+                data.backups.enabled.to_string(),
+            ),
         ];
 
         // Network metadata.
@@ -171,18 +178,57 @@ impl AkamaiProvider {
 }
 
 // Retrieve a token we can use to authenticate future requests to the Linode Metadata Service.
-fn get_token(client: retry::Client) -> Result<String> {
-    let token: String = client
-        .header(
-            HeaderName::from_static("metadata-token-expiry-seconds"),
-            HeaderValue::from_static(TOKEN_TTL),
-        )
-        .put(retry::Raw, AkamaiProvider::endpoint_for("token"), None)
-        .dispatch_put()?
-        .context("get metadata token")?;
-    Ok(token)
+/// Metadata service tokens.
+#[derive(Debug)]
+struct AkamaiTokenSource;
+
+impl retry::TokenSource for AkamaiTokenSource {
+    fn header_name(&self) -> HeaderName {
+        HeaderName::from_static("metadata-token")
+    }
+
+    fn fetch(&self, client: &retry::Client) -> Result<retry::Token> {
+        let token: String = client
+            .clone()
+            .header(
+                HeaderName::from_static("metadata-token-expiry-seconds"),
+                HeaderValue::from_static(TOKEN_TTL),
+            )
+            .put(retry::Raw, AkamaiProvider::endpoint_for("token"), None)
+            .dispatch_put()?
+            .context("get metadata token")?;
+        let ttl = Duration::from_secs(TOKEN_TTL.parse()?);
+        Ok(retry::Token::new(HeaderValue::from_str(&token)?, ttl))
+    }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("AKAMAI_INSTANCE_ID"),
+    instance_type: Some("AKAMAI_INSTANCE_TYPE"),
+    region: Some("AKAMAI_INSTANCE_REGION"),
+    ipv4_private: Some("AKAMAI_PRIVATE_IPV4_0"),
+    ipv4_public: Some("AKAMAI_PUBLIC_IPV4_0"),
+    ipv6: Some("AKAMAI_IPV6_SLAAC"),
+    ..Normalized::NONE
+};
+
+/// Types of attribute values other than strings.
+pub(crate) const VALUE_TYPES: &[(&str, ValueType)] = &[
+    ("AKAMAI_INSTANCE_BACKUPS_ENABLED", ValueType::Boolean),
+    ("AKAMAI_INSTANCE_DISK", ValueType::Number),
+    ("AKAMAI_INSTANCE_GPUS", ValueType::Number),
+    ("AKAMAI_INSTANCE_MEMORY", ValueType::Number),
+    ("AKAMAI_INSTANCE_TAGS", ValueType::Nullable),
+    ("AKAMAI_INSTANCE_TRANSFER", ValueType::Number),
+    ("AKAMAI_INSTANCE_VCPUS", ValueType::Number),
+    ("AKAMAI_IPV6_LINK_LOCAL", ValueType::Nullable),
+    ("AKAMAI_IPV6_SLAAC", ValueType::Nullable),
+];
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[Capability::Attributes, Capability::SshKeys];
+
 impl MetadataProvider for AkamaiProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let attrs = self.parse_attrs()?;
@@ -207,13 +253,10 @@ struct Instance {
     region: String,
     r#type: String,
     tags: Vec<String>,
-    #[allow(dead_code)]
     specs: Specs,
-    #[allow(dead_code)]
     backups: Backups,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize)]
 struct Specs {
     // Total number of virtual CPU cores on the instance.
@@ -237,10 +280,10 @@ struct Specs {
     gpus: u8,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize)]
 struct Backups {
     enabled: bool,
+    #[allow(dead_code)]
     status: Option<String>, // pending, running, complete
 }
 
@@ -126,6 +126,7 @@ fn basic_attributes() {
 
     let v = provider.attributes().unwrap();
     assert_eq!(v, attributes);
+    assert_eq!(aliyun::NORMALIZED_KEYS.missing_keys(&v), Vec::<&str>::new());
 
     server.reset();
     provider.attributes().unwrap_err();
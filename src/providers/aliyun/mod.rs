@@ -8,6 +8,8 @@ use openssh_keys::PublicKey;
 use slog_scope::error;
 use std::collections::{BTreeSet, HashMap};
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
@@ -33,24 +35,27 @@ impl AliyunProvider {
         format!("http://100.100.100.200/latest/meta-data/{name}")
     }
 
-    /// Fetch a metadata attribute from its specific endpoint.
+    /// Fetch metadata attributes from their specific endpoints.
     ///
-    /// Content (if any) is stored into the provided `map`,
-    /// overwriting any previous existing value under the same `key`.
-    fn fetch_attribute(
+    /// Each `(key, endpoint)` pair is fetched concurrently. Content (if any)
+    /// is stored into the provided `map` under the provider-prefixed `key`,
+    /// overwriting any previous existing value.
+    fn fetch_attributes(
         &self,
         map: &mut HashMap<String, String>,
-        key: &str,
-        endpoint: &str,
+        attributes: &[(&str, &str)],
     ) -> Result<()> {
-        let content: Option<String> = self
-            .client
-            .get(retry::Raw, Self::endpoint_for(endpoint))
-            .send()?;
-
-        if let Some(value) = content {
-            if !value.is_empty() {
-                map.insert(key.to_string(), value);
+        let urls = attributes
+            .iter()
+            .map(|(_, endpoint)| Self::endpoint_for(endpoint))
+            .collect();
+        let contents: Vec<Option<String>> = self.client.send_batch(retry::Raw, urls)?;
+
+        for ((key, _), content) in attributes.iter().zip(contents) {
+            if let Some(value) = content {
+                if !value.is_empty() {
+                    map.insert(format!("{PROVIDER_PREFIX}_{key}"), value);
+                }
             }
         }
 
@@ -99,41 +104,44 @@ impl AliyunProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("ALIYUN_INSTANCE_ID"),
+    instance_type: Some("ALIYUN_INSTANCE_TYPE"),
+    region: Some("ALIYUN_REGION_ID"),
+    zone: Some("ALIYUN_ZONE_ID"),
+    ipv4_private: Some("ALIYUN_IPV4_PRIVATE"),
+    ipv4_public: Some("ALIYUN_IPV4_PUBLIC"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+];
+
 impl MetadataProvider for AliyunProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         // See https://www.alibabacloud.com/help/doc-detail/49122.htm.
         let mut out = HashMap::with_capacity(10);
 
-        self.fetch_attribute(&mut out, &format!("{PROVIDER_PREFIX}_EIPV4"), "eipv4")?;
-        self.fetch_attribute(&mut out, &format!("{PROVIDER_PREFIX}_HOSTNAME"), "hostname")?;
-        self.fetch_attribute(&mut out, &format!("{PROVIDER_PREFIX}_IMAGE_ID"), "image-id")?;
-        self.fetch_attribute(
-            &mut out,
-            &format!("{PROVIDER_PREFIX}_INSTANCE_ID"),
-            "instance-id",
-        )?;
-        self.fetch_attribute(
-            &mut out,
-            &format!("{PROVIDER_PREFIX}_INSTANCE_TYPE"),
-            "instance/instance-type",
-        )?;
-        self.fetch_attribute(
-            &mut out,
-            &format!("{PROVIDER_PREFIX}_IPV4_PRIVATE"),
-            "private-ipv4",
-        )?;
-        self.fetch_attribute(
-            &mut out,
-            &format!("{PROVIDER_PREFIX}_IPV4_PUBLIC"),
-            "public-ipv4",
-        )?;
-        self.fetch_attribute(
+        self.fetch_attributes(
             &mut out,
-            &format!("{PROVIDER_PREFIX}_REGION_ID"),
-            "region-id",
+            &[
+                ("EIPV4", "eipv4"),
+                ("HOSTNAME", "hostname"),
+                ("IMAGE_ID", "image-id"),
+                ("INSTANCE_ID", "instance-id"),
+                ("INSTANCE_TYPE", "instance/instance-type"),
+                ("IPV4_PRIVATE", "private-ipv4"),
+                ("IPV4_PUBLIC", "public-ipv4"),
+                ("REGION_ID", "region-id"),
+                ("VPC_ID", "vpc-id"),
+                ("ZONE_ID", "zone-id"),
+            ],
         )?;
-        self.fetch_attribute(&mut out, &format!("{PROVIDER_PREFIX}_VPC_ID"), "vpc-id")?;
-        self.fetch_attribute(&mut out, &format!("{PROVIDER_PREFIX}_ZONE_ID"), "zone-id")?;
 
         Ok(out)
     }
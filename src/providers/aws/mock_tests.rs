@@ -101,6 +101,7 @@ fn test_aws_attributes() {
 
     let v = provider.attributes().unwrap();
     assert_eq!(v, attributes);
+    assert_eq!(aws::NORMALIZED_KEYS.missing_keys(&v), Vec::<&str>::new());
 
     server.reset();
     provider.attributes().unwrap_err();
@@ -179,3 +180,52 @@ fn test_aws_imds_version2() {
     server.reset();
     provider.attributes().unwrap_err();
 }
+
+#[test]
+fn test_aws_imds_token_refresh() {
+    let mut server = mockito::Server::new();
+    let client = crate::retry::Client::try_new()
+        .context("failed to create http client")
+        .unwrap()
+        .max_retries(0)
+        .return_on_404(true)
+        .mock_base_url(server.url());
+
+    let fetched = std::sync::atomic::AtomicUsize::new(0);
+    let put_token = server
+        .mock("PUT", "/latest/api/token")
+        .match_header("X-aws-ec2-metadata-token-ttl-seconds", "21600")
+        .with_status(200)
+        .with_body_from_request(move |_| {
+            let n = fetched.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            format!("token-{n}").into_bytes()
+        })
+        .expect(2)
+        .create();
+
+    // first token is rejected, e.g. because it expired
+    let endpoint = "/2021-01-03/meta-data/hostname";
+    let rejected = server
+        .mock("GET", endpoint)
+        .match_header("X-aws-ec2-metadata-token", "token-0")
+        .with_status(401)
+        .expect(1)
+        .create();
+    let accepted = server
+        .mock("GET", endpoint)
+        .match_header("X-aws-ec2-metadata-token", "token-1")
+        .with_status(200)
+        .with_body("test-hostname")
+        .expect(2)
+        .create();
+
+    let provider = aws::AwsProvider::with_client(client).unwrap();
+    for _ in 0..2 {
+        let v = provider.hostname().unwrap();
+        assert_eq!(v, Some("test-hostname".to_string()));
+    }
+
+    put_token.assert();
+    rejected.assert();
+    accepted.assert();
+}
@@ -16,6 +16,7 @@
 //!
 
 use std::collections::HashMap;
+use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
 use openssh_keys::PublicKey;
@@ -23,12 +24,17 @@ use reqwest::header;
 use serde::Deserialize;
 use slog_scope::warn;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
 #[cfg(test)]
 mod mock_tests;
 
+/// Lifetime of IMDSv2 session tokens, in seconds.
+const IMDSV2_TOKEN_TTL: u64 = 21600;
+
 #[allow(non_snake_case)]
 #[derive(Debug, Deserialize)]
 struct InstanceIdDoc {
@@ -47,25 +53,7 @@ impl AwsProvider {
     }
 
     fn with_client(client: retry::Client) -> Result<AwsProvider> {
-        let mut client = client;
-        let token = AwsProvider::fetch_imdsv2_token(client.clone());
-
-        // If IMDSv2 token is fetched successfully, set the header.
-        // Otherwise, proceed with IMDSv1 mechanism.
-        match token {
-            Ok(t) => {
-                client = client.header(
-                    header::HeaderName::from_bytes(b"X-aws-ec2-metadata-token")
-                        .context("setting header name for aws imdsv2 metadata")?,
-                    header::HeaderValue::from_bytes(t.as_bytes())
-                        .context("setting header value for aws imdsv2 metadata")?,
-                );
-            }
-            Err(err) => {
-                warn!("failed to fetch aws imdsv2 session token with: {}", err);
-            }
-        }
-
+        let client = client.token_source(Imdsv2TokenSource);
         Ok(AwsProvider { client })
     }
 
@@ -80,25 +68,6 @@ impl AwsProvider {
         }
     }
 
-    fn fetch_imdsv2_token(client: retry::Client) -> Result<String> {
-        let token: String = client
-            .header(
-                header::HeaderName::from_bytes(b"X-aws-ec2-metadata-token-ttl-seconds")
-                    .context("setting header name for aws imdsv2 token")?,
-                header::HeaderValue::from_bytes(b"21600")
-                    .context("setting header value for aws imdsv2 token")?,
-            )
-            .put(
-                retry::Raw,
-                // NOTE(zonggen): Use `latest` here since other versions would return "403 - Forbidden"
-                AwsProvider::endpoint_for("api/token", true),
-                None,
-            )
-            .dispatch_put()?
-            .context("unwrapping aws imdsv2 token")?;
-        Ok(token)
-    }
-
     fn fetch_ssh_keys(&self) -> Result<Vec<String>> {
         let keydata: Option<String> = self
             .client
@@ -133,40 +102,99 @@ impl AwsProvider {
     }
 }
 
-impl MetadataProvider for AwsProvider {
-    fn attributes(&self) -> Result<HashMap<String, String>> {
-        let mut out = HashMap::with_capacity(6);
+/// IMDSv2 session tokens.
+///
+/// If a token cannot be fetched, requests proceed with the IMDSv1
+/// mechanism.
+#[derive(Debug)]
+struct Imdsv2TokenSource;
 
-        let add_value = |map: &mut HashMap<_, _>, key: &str, name| -> Result<()> {
-            let value = self
-                .client
-                .get(retry::Raw, AwsProvider::endpoint_for(name, false))
-                .send()?;
+impl retry::TokenSource for Imdsv2TokenSource {
+    fn header_name(&self) -> header::HeaderName {
+        header::HeaderName::from_static("x-aws-ec2-metadata-token")
+    }
 
-            if let Some(value) = value {
-                map.insert(key.to_string(), value);
+    fn fetch(&self, client: &retry::Client) -> Result<retry::Token> {
+        let ttl = Duration::from_secs(IMDSV2_TOKEN_TTL);
+        let token: Result<String> = client
+            .clone()
+            .header(
+                header::HeaderName::from_static("x-aws-ec2-metadata-token-ttl-seconds"),
+                header::HeaderValue::from(IMDSV2_TOKEN_TTL),
+            )
+            .put(
+                retry::Raw,
+                // NOTE(zonggen): Use `latest` here since other versions would return "403 - Forbidden"
+                AwsProvider::endpoint_for("api/token", true),
+                None,
+            )
+            .dispatch_put()
+            .and_then(|t| t.context("unwrapping aws imdsv2 token"));
+
+        match token {
+            Ok(t) => {
+                let value = header::HeaderValue::from_bytes(t.as_bytes())
+                    .context("setting header value for aws imdsv2 metadata")?;
+                Ok(retry::Token::new(value, ttl))
             }
+            Err(err) => {
+                warn!("failed to fetch aws imdsv2 session token with: {:#}", err);
+                Ok(retry::Token::absent(ttl))
+            }
+        }
+    }
+}
+
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("AWS_INSTANCE_ID"),
+    instance_type: Some("AWS_INSTANCE_TYPE"),
+    region: Some("AWS_REGION"),
+    zone: Some("AWS_AVAILABILITY_ZONE"),
+    ipv4_private: Some("AWS_IPV4_LOCAL"),
+    ipv4_public: Some("AWS_IPV4_PUBLIC"),
+    ipv6: Some("AWS_IPV6"),
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::KubeletConfig,
+];
 
-            Ok(())
-        };
-
-        add_value(&mut out, "AWS_INSTANCE_ID", "meta-data/instance-id")?;
-        add_value(&mut out, "AWS_INSTANCE_TYPE", "meta-data/instance-type")?;
-        add_value(&mut out, "AWS_IPV4_LOCAL", "meta-data/local-ipv4")?;
-        add_value(&mut out, "AWS_IPV4_PUBLIC", "meta-data/public-ipv4")?;
-        add_value(&mut out, "AWS_IPV6", "meta-data/ipv6")?;
-        add_value(
-            &mut out,
-            "AWS_AVAILABILITY_ZONE",
-            "meta-data/placement/availability-zone",
-        )?;
-        add_value(
-            &mut out,
-            "AWS_AVAILABILITY_ZONE_ID",
-            "meta-data/placement/availability-zone-id",
-        )?;
-        add_value(&mut out, "AWS_HOSTNAME", "meta-data/hostname")?;
-        add_value(&mut out, "AWS_PUBLIC_HOSTNAME", "meta-data/public-hostname")?;
+impl MetadataProvider for AwsProvider {
+    fn attributes(&self) -> Result<HashMap<String, String>> {
+        let attributes = [
+            ("AWS_INSTANCE_ID", "meta-data/instance-id"),
+            ("AWS_INSTANCE_TYPE", "meta-data/instance-type"),
+            ("AWS_IPV4_LOCAL", "meta-data/local-ipv4"),
+            ("AWS_IPV4_PUBLIC", "meta-data/public-ipv4"),
+            ("AWS_IPV6", "meta-data/ipv6"),
+            (
+                "AWS_AVAILABILITY_ZONE",
+                "meta-data/placement/availability-zone",
+            ),
+            (
+                "AWS_AVAILABILITY_ZONE_ID",
+                "meta-data/placement/availability-zone-id",
+            ),
+            ("AWS_HOSTNAME", "meta-data/hostname"),
+            ("AWS_PUBLIC_HOSTNAME", "meta-data/public-hostname"),
+        ];
+        let urls = attributes
+            .iter()
+            .map(|(_, name)| AwsProvider::endpoint_for(name, false))
+            .collect();
+        let values: Vec<Option<String>> = self.client.send_batch(retry::Raw, urls)?;
+
+        let mut out = HashMap::with_capacity(attributes.len() + 1);
+        for ((key, _), value) in attributes.iter().zip(values) {
+            if let Some(value) = value {
+                out.insert(key.to_string(), value);
+            }
+        }
 
         let region = self
             .client
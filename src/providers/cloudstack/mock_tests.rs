@@ -43,3 +43,36 @@ fn test_ssh_keys_404_ok() {
     server.reset();
     provider.ssh_keys().unwrap_err();
 }
+
+#[test]
+fn test_attributes() {
+    let mut server = mockito::Server::new();
+    let mut provider = CloudstackNetwork::try_new().unwrap();
+    provider.client = provider.client.max_retries(0).mock_base_url(server.url());
+
+    let endpoints = maplit::btreemap! {
+        "/latest/meta-data/instance-id" => "7a2c3b3e-2f4e-4a6b-9d2a-0d4c5e6f7a8b",
+        "/latest/meta-data/availability-zone" => "zone-1",
+        "/latest/meta-data/public-ipv4" => "192.0.2.10",
+        "/latest/meta-data/local-ipv4" => "10.1.1.10",
+        "/latest/meta-data/service-offering" => "Small Instance",
+    };
+    for (endpoint, body) in endpoints {
+        server
+            .mock("GET", endpoint)
+            .with_status(200)
+            .with_body(body)
+            .create();
+    }
+    server
+        .mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/latest/meta-data/.*$".to_string()),
+        )
+        .with_status(404)
+        .create();
+
+    let v = provider.attributes().unwrap();
+    assert_eq!(v.len(), 5);
+    assert_eq!(super::NORMALIZED_KEYS.missing_keys(&v), Vec::<&str>::new());
+}
@@ -1,6 +1,22 @@
 //! Metadata fetchers for the cloudstack provider
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
+
 pub mod configdrive;
 #[cfg(test)]
 mod mock_tests;
 pub mod network;
+
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("CLOUDSTACK_INSTANCE_ID"),
+    instance_type: Some("CLOUDSTACK_SERVICE_OFFERING"),
+    zone: Some("CLOUDSTACK_AVAILABILITY_ZONE"),
+    ipv4_private: Some("CLOUDSTACK_IPV4_LOCAL"),
+    ipv4_public: Some("CLOUDSTACK_IPV4_PUBLIC"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[Capability::Attributes, Capability::SshKeys];
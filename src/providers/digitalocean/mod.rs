@@ -24,6 +24,8 @@ use openssh_keys::PublicKey;
 use pnet_base::MacAddr;
 use serde::Deserialize;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::network;
 use crate::providers::MetadataProvider;
 use crate::retry;
@@ -265,6 +267,23 @@ impl DigitalOceanProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    region: Some("DIGITALOCEAN_REGION"),
+    ipv4_private: Some("DIGITALOCEAN_IPV4_PRIVATE_0"),
+    ipv4_public: Some("DIGITALOCEAN_IPV4_PUBLIC_0"),
+    ipv6: Some("DIGITALOCEAN_IPV6_PUBLIC_0"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::Networks,
+];
+
 impl MetadataProvider for DigitalOceanProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         Ok(self.parse_attrs().into_iter().collect())
@@ -289,3 +308,42 @@ impl MetadataProvider for DigitalOceanProvider {
         self.parse_network()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes() {
+        let data = r#"{
+            "hostname": "droplet-1",
+            "region": "nyc3",
+            "public_keys": [],
+            "dns": {"nameservers": ["67.207.67.2"]},
+            "interfaces": {
+                "public": [{
+                    "ipv4": {"ip_address": "192.0.2.10", "netmask": "255.255.240.0", "gateway": "192.0.2.1"},
+                    "ipv6": {"ip_address": "2001:db8::10", "cidr": 64, "gateway": "2001:db8::1"},
+                    "mac": "22:36:8b:9e:1c:01",
+                    "type": "public"
+                }],
+                "private": [{
+                    "ipv4": {"ip_address": "10.132.0.2", "netmask": "255.255.0.0", "gateway": "10.132.0.1"},
+                    "mac": "22:36:8b:9e:1c:02",
+                    "type": "private"
+                }]
+            }
+        }"#;
+        let provider: DigitalOceanProvider = serde_json::from_str(data).unwrap();
+
+        let attributes = provider.attributes().unwrap();
+        assert_eq!(
+            attributes.get("DIGITALOCEAN_IPV4_PRIVATE_0"),
+            Some(&"10.132.0.2".to_string())
+        );
+        assert_eq!(
+            NORMALIZED_KEYS.missing_keys(&attributes),
+            Vec::<&str>::new()
+        );
+    }
+}
@@ -109,6 +109,10 @@ fn basic_attributes() {
 
     let v = provider.attributes().unwrap();
     assert_eq!(v, attributes);
+    assert_eq!(
+        exoscale::NORMALIZED_KEYS.missing_keys(&v),
+        Vec::<&str>::new()
+    );
 
     server.reset();
     provider.attributes().unwrap_err();
@@ -21,6 +21,8 @@ use std::collections::HashMap;
 use anyhow::Result;
 use openssh_keys::PublicKey;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
@@ -44,31 +46,48 @@ impl ExoscaleProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("EXOSCALE_INSTANCE_ID"),
+    instance_type: Some("EXOSCALE_SERVICE_OFFERING"),
+    zone: Some("EXOSCALE_AVAILABILITY_ZONE"),
+    ipv4_private: Some("EXOSCALE_LOCAL_IPV4"),
+    ipv4_public: Some("EXOSCALE_PUBLIC_IPV4"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+];
+
 impl MetadataProvider for ExoscaleProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
-        let mut out = HashMap::with_capacity(9);
-        let add_value = |map: &mut HashMap<_, _>, key: &str, name| -> Result<()> {
-            let value = self
-                .client
-                .get(retry::Raw, self.endpoint_for(name))
-                .send()?;
-
+        let attributes = [
+            ("EXOSCALE_INSTANCE_ID", "instance-id"),
+            ("EXOSCALE_LOCAL_HOSTNAME", "local-hostname"),
+            ("EXOSCALE_PUBLIC_HOSTNAME", "public-hostname"),
+            ("EXOSCALE_AVAILABILITY_ZONE", "availability-zone"),
+            ("EXOSCALE_PUBLIC_IPV4", "public-ipv4"),
+            ("EXOSCALE_LOCAL_IPV4", "local-ipv4"),
+            ("EXOSCALE_SERVICE_OFFERING", "service-offering"),
+            ("EXOSCALE_CLOUD_IDENTIFIER", "cloud-identifier"),
+            ("EXOSCALE_VM_ID", "vm-id"),
+        ];
+        let urls = attributes
+            .iter()
+            .map(|(_, name)| self.endpoint_for(name))
+            .collect();
+        let values: Vec<Option<String>> = self.client.send_batch(retry::Raw, urls)?;
+
+        let mut out = HashMap::with_capacity(attributes.len());
+        for ((key, _), value) in attributes.iter().zip(values) {
             if let Some(value) = value {
-                map.insert(key.to_string(), value);
+                out.insert(key.to_string(), value);
             }
-
-            Ok(())
-        };
-
-        add_value(&mut out, "EXOSCALE_INSTANCE_ID", "instance-id")?;
-        add_value(&mut out, "EXOSCALE_LOCAL_HOSTNAME", "local-hostname")?;
-        add_value(&mut out, "EXOSCALE_PUBLIC_HOSTNAME", "public-hostname")?;
-        add_value(&mut out, "EXOSCALE_AVAILABILITY_ZONE", "availability-zone")?;
-        add_value(&mut out, "EXOSCALE_PUBLIC_IPV4", "public-ipv4")?;
-        add_value(&mut out, "EXOSCALE_LOCAL_IPV4", "local-ipv4")?;
-        add_value(&mut out, "EXOSCALE_SERVICE_OFFERING", "service-offering")?;
-        add_value(&mut out, "EXOSCALE_CLOUD_IDENTIFIER", "cloud-identifier")?;
-        add_value(&mut out, "EXOSCALE_VM_ID", "vm-id")?;
+        }
 
         Ok(out)
     }
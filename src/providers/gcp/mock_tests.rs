@@ -35,13 +35,18 @@ fn basic_attributes() {
     let hostname = "test-hostname";
     let ip_external = "test-ip-external";
     let ip_local = "test-ip-local";
-    let machine_type = "test-machine-type";
+    let machine_type = "projects/123456/machineTypes/n1-standard-1";
+    let name = "test-name";
+    let project_id = "test-project";
 
     let endpoints = maplit::btreemap! {
         "/computeMetadata/v1/instance/hostname" => hostname,
         "/computeMetadata/v1/instance/network-interfaces/0/access-configs/0/external-ip" => ip_external,
         "/computeMetadata/v1/instance/network-interfaces/0/ip" => ip_local,
         "/computeMetadata/v1/instance/machine-type" => machine_type,
+        "/computeMetadata/v1/instance/name" => name,
+        "/computeMetadata/v1/instance/zone" => "projects/123456/zones/us-central1-a",
+        "/computeMetadata/v1/project/project-id" => project_id,
     };
     let mut server = mockito::Server::new();
     for (endpoint, body) in endpoints {
@@ -56,7 +61,10 @@ fn basic_attributes() {
         "GCP_HOSTNAME".to_string() => hostname.to_string(),
         "GCP_IP_EXTERNAL_0".to_string() => ip_external.to_string(),
         "GCP_IP_LOCAL_0".to_string() => ip_local.to_string(),
+        "GCP_INSTANCE_NAME".to_string() => name.to_string(),
         "GCP_MACHINE_TYPE".to_string() => machine_type.to_string(),
+        "GCP_PROJECT_ID".to_string() => project_id.to_string(),
+        "GCP_ZONE".to_string() => "us-central1-a".to_string(),
     };
 
     let client = crate::retry::Client::try_new()
@@ -68,6 +76,10 @@ fn basic_attributes() {
 
     let v = provider.attributes().unwrap();
     assert_eq!(v, attributes);
+    assert_eq!(gcp::NORMALIZED_KEYS.missing_keys(&v), Vec::<&str>::new());
+    let normalized = crate::metadata::normalized_attributes("gcp", &v);
+    assert_eq!(normalized["INSTANCE_TYPE"], "n1-standard-1");
+    assert_eq!(normalized["ZONE"], "us-central1-a");
 
     server.reset();
     provider.attributes().unwrap_err();
@@ -19,6 +19,8 @@ use openssh_keys::PublicKey;
 use reqwest::header::{HeaderName, HeaderValue};
 use std::collections::HashMap;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
@@ -111,37 +113,57 @@ impl GcpProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_type: Some("GCP_MACHINE_TYPE"),
+    zone: Some("GCP_ZONE"),
+    ipv4_private: Some("GCP_IP_LOCAL_0"),
+    ipv4_public: Some("GCP_IP_EXTERNAL_0"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::KubeletConfig,
+];
+
 impl MetadataProvider for GcpProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
-        let mut out = HashMap::with_capacity(4);
-
-        let add_value = |map: &mut HashMap<_, _>, key: &str, name| -> Result<()> {
-            let value: Option<String> = self
-                .client
-                .get(retry::Raw, GcpProvider::endpoint_for(name))
-                .send()?;
-
-            if let Some(value) = value {
+        let attributes = [
+            ("GCP_HOSTNAME", "instance/hostname"),
+            (
+                "GCP_IP_EXTERNAL_0",
+                "instance/network-interfaces/0/access-configs/0/external-ip",
+            ),
+            ("GCP_INSTANCE_NAME", "instance/name"),
+            ("GCP_IP_LOCAL_0", "instance/network-interfaces/0/ip"),
+            ("GCP_MACHINE_TYPE", "instance/machine-type"),
+            ("GCP_PROJECT_ID", "project/project-id"),
+            ("GCP_ZONE", "instance/zone"),
+        ];
+        let urls = attributes
+            .iter()
+            .map(|(_, name)| GcpProvider::endpoint_for(name))
+            .collect();
+        let values: Vec<Option<String>> = self.client.send_batch(retry::Raw, urls)?;
+
+        let mut out = HashMap::with_capacity(attributes.len());
+        for ((key, _), value) in attributes.iter().zip(values) {
+            if let Some(mut value) = value {
+                if *key == "GCP_ZONE" {
+                    // drop the `projects/<number>/zones/` prefix
+                    if let Some((_, zone)) = value.rsplit_once('/') {
+                        value = zone.to_string();
+                    }
+                }
                 if !value.is_empty() {
-                    map.insert(key.to_string(), value);
+                    out.insert(key.to_string(), value);
                 }
             }
-
-            Ok(())
-        };
-
-        add_value(&mut out, "GCP_HOSTNAME", "instance/hostname")?;
-        add_value(
-            &mut out,
-            "GCP_IP_EXTERNAL_0",
-            "instance/network-interfaces/0/access-configs/0/external-ip",
-        )?;
-        add_value(
-            &mut out,
-            "GCP_IP_LOCAL_0",
-            "instance/network-interfaces/0/ip",
-        )?;
-        add_value(&mut out, "GCP_MACHINE_TYPE", "instance/machine-type")?;
+        }
 
         Ok(out)
     }
@@ -90,6 +90,10 @@ vendor_data: "blah blah blah""#
     mock_metadata.assert();
     mock_networks.assert();
     assert_eq!(actual, expected);
+    assert_eq!(
+        super::NORMALIZED_KEYS.missing_keys(&actual),
+        Vec::<&str>::new()
+    );
 }
 
 #[test]
@@ -21,6 +21,8 @@ use anyhow::Result;
 use openssh_keys::PublicKey;
 use serde::Deserialize;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::retry;
 
 use super::MetadataProvider;
@@ -49,6 +51,24 @@ impl HetznerProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("HETZNER_INSTANCE_ID"),
+    region: Some("HETZNER_REGION"),
+    zone: Some("HETZNER_AVAILABILITY_ZONE"),
+    ipv4_private: Some("HETZNER_PRIVATE_IPV4_0"),
+    ipv4_public: Some("HETZNER_PUBLIC_IPV4"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::KubeletConfig,
+];
+
 impl MetadataProvider for HetznerProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let metadata: Metadata = self
@@ -20,6 +20,8 @@ use std::str;
 
 use tempfile::TempDir;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 
 use mailparse::*;
@@ -158,6 +160,19 @@ impl IBMGen2Provider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("IBMCLOUD_INSTANCE_ID"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+];
+
 impl MetadataProvider for IBMGen2Provider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let metadata = self.read_metadata()?;
@@ -241,6 +256,7 @@ foo:      ba:r
             attrs.get("IBMCLOUD_LOCAL_HOSTNAME"),
             Some(&"test_instance-vpc-gen2".to_string())
         );
+        assert_eq!(NORMALIZED_KEYS.missing_keys(&attrs), Vec::<&str>::new());
     }
 
     #[test]
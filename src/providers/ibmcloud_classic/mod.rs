@@ -23,6 +23,9 @@ use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
+use crate::attributes::Normalized;
+use crate::errors::ErrorKind;
+use crate::metadata::Capability;
 use crate::network;
 use crate::providers::MetadataProvider;
 
@@ -157,7 +160,9 @@ impl IBMClassicProvider {
     ///
     /// Metadata file contains a JSON object, corresponding to `MetaDataJSON`.
     fn parse_metadata<T: Read>(input: BufReader<T>) -> Result<MetaDataJSON> {
-        serde_json::from_reader(input).context("failed to parse JSON metadata")
+        serde_json::from_reader(input)
+            .context("failed to parse JSON metadata")
+            .context(ErrorKind::MalformedMetadata)
     }
 
     /// Extract supported metadata values and convert to Afterburn attributes.
@@ -194,7 +199,9 @@ impl IBMClassicProvider {
     ///
     /// Network configuration file contains a JSON object, corresponding to `NetworkDataJSON`.
     fn parse_network_data<T: Read>(input: BufReader<T>) -> Result<NetworkDataJSON> {
-        serde_json::from_reader(input).context("failed to parse JSON network data")
+        serde_json::from_reader(input)
+            .context("failed to parse JSON network data")
+            .context(ErrorKind::MalformedMetadata)
     }
 
     /// Transform network JSON data into a set of interface configurations.
@@ -265,6 +272,19 @@ impl IBMClassicProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("IBMCLOUD_CLASSIC_INSTANCE_ID"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::Networks,
+];
+
 impl MetadataProvider for IBMClassicProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let metadata = self.read_metadata()?;
@@ -347,6 +367,7 @@ mod tests {
             attrs.get("IBMCLOUD_CLASSIC_LOCAL_HOSTNAME"),
             Some(&"test_instance-classic".to_string())
         );
+        assert_eq!(NORMALIZED_KEYS.missing_keys(&attrs), Vec::<&str>::new());
     }
 
     #[test]
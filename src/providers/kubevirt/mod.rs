@@ -21,6 +21,9 @@ use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
+use crate::attributes::Normalized;
+use crate::errors::ErrorKind;
+use crate::metadata::Capability;
 use crate::network;
 use crate::providers::MetadataProvider;
 
@@ -95,7 +98,9 @@ impl KubeVirtProvider {
     ///
     /// Metadata file contains a JSON object, corresponding to `MetaDataJSON`.
     fn parse_metadata<T: Read>(input: BufReader<T>) -> Result<MetaDataJSON> {
-        serde_json::from_reader(input).context("failed to parse JSON metadata")
+        serde_json::from_reader(input)
+            .context("failed to parse JSON metadata")
+            .context(ErrorKind::MalformedMetadata)
     }
 
     /// Extract supported metadata values and convert to Afterburn attributes.
@@ -134,6 +139,20 @@ impl KubeVirtProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("KUBEVIRT_INSTANCE_ID"),
+    instance_type: Some("KUBEVIRT_INSTANCE_TYPE"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+];
+
 impl MetadataProvider for KubeVirtProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let metadata = self.read_metadata()?;
@@ -243,6 +262,7 @@ mod tests {
             attrs.get("KUBEVIRT_INSTANCE_TYPE"),
             Some(&"some_type".to_string())
         );
+        assert_eq!(NORMALIZED_KEYS.missing_keys(&attrs), Vec::<&str>::new());
     }
 
     #[test]
@@ -200,6 +200,16 @@ fn test_vmsize() {
         .with_status(200)
         .create();
 
+    let endpoint = "/metadata/instance/compute?api-version=2017-12-01&format=json";
+    let m_compute = server
+        .mock("GET", endpoint)
+        .match_header("Metadata", "true")
+        .with_body(
+            r#"{"location": "westeurope", "name": "testname", "resourceGroupName": "MyGroup", "subscriptionId": "sub-1", "zone": "", "vmSize": "testvmsize"}"#,
+        )
+        .with_status(200)
+        .create();
+
     let client = retry::Client::try_new()
         .unwrap()
         .mock_base_url(server.url());
@@ -210,8 +220,46 @@ fn test_vmsize() {
     m_version.assert();
 
     m_vmsize.assert();
+    m_compute.assert();
     let vmsize = r.unwrap();
     assert_eq!(vmsize, testvmsize);
+    assert_eq!(attributes["AZURE_LOCATION"], "westeurope");
+    assert_eq!(attributes["AZURE_RESOURCE_GROUP"], "MyGroup");
+    assert_eq!(attributes["AZURE_SUBSCRIPTION_ID"], "sub-1");
+    assert_eq!(attributes["AZURE_VM_NAME"], "testname");
+    assert!(!attributes.contains_key("AZURE_ZONE"));
+    assert!(!attributes.contains_key("AZURE_VMSS_NAME"));
+    assert_eq!(
+        azure::NORMALIZED_KEYS.missing_keys(&attributes),
+        Vec::<&str>::new()
+    );
+
+    // Compute metadata is optional.
+    server.reset();
+    let m_version = mock_fab_version(&mut server);
+    let m_vmsize = server
+        .mock(
+            "GET",
+            "/metadata/instance/compute/vmSize?api-version=2017-08-01&format=text",
+        )
+        .with_body(testvmsize)
+        .create();
+    let m_compute = server
+        .mock("GET", endpoint)
+        .with_status(500)
+        .expect(1)
+        .create();
+    let client = retry::Client::try_new()
+        .unwrap()
+        .max_retries(0)
+        .mock_base_url(server.url());
+    let provider = azure::Azure::with_client(Some(client)).unwrap();
+    let attributes = provider.attributes().unwrap();
+    m_version.assert();
+    m_vmsize.assert();
+    m_compute.assert();
+    assert_eq!(attributes["AZURE_VMSIZE"], testvmsize);
+    assert!(!attributes.contains_key("AZURE_LOCATION"));
 
     server.reset();
 
@@ -27,6 +27,8 @@ use serde::Deserialize;
 use slog_scope::warn;
 
 use self::crypto::x509;
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 use nix::unistd::Uid;
@@ -94,6 +96,18 @@ pub struct Azure {
     endpoint: IpAddr,
 }
 
+/// Partial IMDS compute metadata.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Compute {
+    location: String,
+    name: String,
+    resource_group_name: String,
+    subscription_id: String,
+    vm_scale_set_name: String,
+    zone: String,
+}
+
 #[derive(Debug, Default)]
 struct Attributes {
     pub virtual_ipv4: Option<IpAddr>,
@@ -167,6 +181,10 @@ impl Azure {
 
     #[cfg(not(test))]
     fn get_fabric_address() -> IpAddr {
+        // the address is replaced anyway when endpoints are overridden
+        if retry::base_url_override().is_some() {
+            return IpAddr::from(FALLBACK_WIRESERVER_ADDR);
+        }
         // try to fetch from dhcp, else use fallback; this is similar to what WALinuxAgent does
         Azure::get_fabric_address_from_dhcp().unwrap_or_else(|e| {
             warn!("Failed to get fabric address from DHCP: {}", e);
@@ -351,6 +369,23 @@ impl Azure {
         Ok(vmsize)
     }
 
+    fn fetch_compute(&self) -> Result<Compute> {
+        const COMPUTE_URL: &str = "metadata/instance/compute?api-version=2017-12-01&format=json";
+        let url = format!("{}/{}", Self::metadata_endpoint(), COMPUTE_URL);
+
+        let compute = self
+            .client
+            .clone()
+            .header(
+                HeaderName::from_static("metadata"),
+                HeaderValue::from_static("true"),
+            )
+            .get(retry::Json, url)
+            .send()
+            .context("failed to get compute metadata")?;
+        Ok(compute.unwrap_or_default())
+    }
+
     /// Report ready state to the WireServer.
     ///
     /// This is used to signal to the cloud platform that the VM has
@@ -370,11 +405,36 @@ impl Azure {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_type: Some("AZURE_VMSIZE"),
+    region: Some("AZURE_LOCATION"),
+    ipv4_private: Some("AZURE_IPV4_DYNAMIC"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::BootCheckin,
+    Capability::KubeletConfig,
+];
+
 impl MetadataProvider for Azure {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let attributes = self.get_attributes()?;
         let vmsize = self.fetch_vmsize()?;
-        let mut out = HashMap::with_capacity(3);
+        // Compute metadata only adds attributes, don't fail without it.
+        let compute = self.fetch_compute().unwrap_or_else(|e| {
+            warn!(
+                "failed to fetch compute metadata, omitting its attributes: {:#}",
+                e
+            );
+            Compute::default()
+        });
+        let mut out = HashMap::with_capacity(8);
 
         if let Some(virtual_ipv4) = attributes.virtual_ipv4 {
             out.insert("AZURE_IPV4_VIRTUAL".to_string(), virtual_ipv4.to_string());
@@ -386,6 +446,19 @@ impl MetadataProvider for Azure {
 
         out.insert("AZURE_VMSIZE".to_string(), vmsize);
 
+        for (key, value) in [
+            ("AZURE_LOCATION", compute.location),
+            ("AZURE_RESOURCE_GROUP", compute.resource_group_name),
+            ("AZURE_SUBSCRIPTION_ID", compute.subscription_id),
+            ("AZURE_VM_NAME", compute.name),
+            ("AZURE_VMSS_NAME", compute.vm_scale_set_name),
+            ("AZURE_ZONE", compute.zone),
+        ] {
+            if !value.is_empty() {
+                out.insert(key.to_string(), value);
+            }
+        }
+
         Ok(out)
     }
 
@@ -26,6 +26,7 @@ use serde::Deserialize;
 use slog_scope::warn;
 
 use self::crypto::x509;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 use nix::unistd::Uid;
@@ -312,6 +313,13 @@ impl AzureStack {
     }
 }
 
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::BootCheckin,
+];
+
 impl MetadataProvider for AzureStack {
     fn hostname(&self) -> Result<Option<String>> {
         self.fetch_hostname()
@@ -44,32 +44,29 @@ pub mod scaleway;
 pub mod vmware;
 pub mod vultr;
 
+use crate::attributes::{self, AttributesFormat};
+use crate::config::{AttributesConfig, SshKeysConfig};
+use crate::errors::ErrorKind;
+use crate::kubelet::NodeIdentity;
+use crate::metadata;
 use crate::network;
+use crate::util::{self, AtomicWrite, FileOwner, WriteOutcome};
 use anyhow::{anyhow, Context, Result};
 use libsystemd::logging;
 use nix::unistd;
 use openssh_keys::PublicKey;
 use slog_scope::warn;
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::prelude::*;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use uzers::{self, User};
 
 /// Message ID markers for authorized-keys entries in journal.
 const AFTERBURN_SSH_AUTHORIZED_KEYS_ADDED_MESSAGEID: &str = "0f7d7a502f2d433caa1323440a6b4190";
 const AFTERBURN_SSH_AUTHORIZED_KEYS_REMOVED_MESSAGEID: &str = "f8b91c53f5544868a3a10d0dcf68e9ea";
 
-fn create_file(filename: &str) -> Result<File> {
-    let file_path = Path::new(&filename);
-    // create the directories if they don't exist
-    let folder = file_path
-        .parent()
-        .ok_or_else(|| anyhow!("could not get parent directory of {:?}", file_path))?;
-    fs::create_dir_all(folder).with_context(|| format!("failed to create directory {folder:?}"))?;
-    // create (or truncate) the file we want to write to
-    File::create(file_path).with_context(|| format!("failed to create file {file_path:?}"))
-}
+/// File name of the netplan config, in the netplan config directory.
+const NETPLAN_CONFIG_FILE: &str = "50-afterburn.yaml";
 
 /// Add a message to the journal logging SSH key additions; this
 /// will be used by at least Fedora CoreOS to display in the console
@@ -93,82 +90,73 @@ fn write_ssh_key_journal_entry(log: logging::Priority, name: &str, path: &str, a
     }
 }
 
-fn write_ssh_keys(user: User, ssh_keys: Vec<PublicKey>) -> Result<()> {
-    use std::io::ErrorKind::NotFound;
+/// Return the path to the authorized keys fragment managed by Afterburn for `user`.
+pub(crate) fn ssh_keys_path(user: &User) -> PathBuf {
     use uzers::os::unix::UserExt;
 
+    user.home_dir()
+        .join(".ssh")
+        .join("authorized_keys.d")
+        .join(SSH_KEYS_FILE)
+}
+
+/// Log the outcome of writing an output file.
+fn log_write(path: &Path, outcome: WriteOutcome) {
+    match outcome {
+        WriteOutcome::Written => slog_scope::debug!("wrote {}", path.display()),
+        WriteOutcome::Unchanged => slog_scope::debug!("{} unchanged, skipped", path.display()),
+    }
+}
+
+/// Look up a user by name.
+pub(crate) fn get_user(username: &str) -> Result<User> {
+    uzers::get_user_by_name(username)
+        .ok_or_else(|| anyhow!("could not find user with username {:?}", username))
+}
+
+/// File name of the authorized keys fragment.
+const SSH_KEYS_FILE: &str = "afterburn";
+
+/// Render the authorized keys fragment for `ssh_keys`, or `None` if there
+/// are no keys and the fragment should be removed.
+pub(crate) fn render_ssh_keys(ssh_keys: &[PublicKey]) -> Option<String> {
+    (!ssh_keys.is_empty()).then(|| ssh_keys.iter().map(|key| format!("{key}\n")).collect())
+}
+
+/// Return the writer of the authorized keys fragment for `user`.
+pub(crate) fn ssh_keys_writer(user: &User) -> AtomicWrite {
+    // only readable by the user
+    AtomicWrite::default().mode(0o600).owner(Some(FileOwner {
+        uid: user.uid(),
+        gid: user.primary_group_id(),
+    }))
+}
+
+/// Write SSH keys for `user`, removing the fragment if there are no keys.
+pub(crate) fn write_ssh_keys(user: User, ssh_keys: Vec<PublicKey>) -> Result<()> {
     // switch users
     let _guard = uzers::switch::switch_user_group(user.uid(), user.primary_group_id())
         .context("failed to switch user/group")?;
 
-    // get paths
-    let dir_path = user.home_dir().join(".ssh").join("authorized_keys.d");
-    let file_name = "afterburn";
-    let file_path = &dir_path.join(file_name);
+    let file_path = &ssh_keys_path(&user);
 
     // stringify for logging
     let username = user.name().to_string_lossy();
     let file_path_str = file_path.to_string_lossy();
 
-    if !ssh_keys.is_empty() {
-        // ensure directory exists
-        fs::create_dir_all(&dir_path)
-            .with_context(|| format!("failed to create directory {:?}", &dir_path))?;
-
-        // create temporary file
-        let mut temp_file = tempfile::Builder::new()
-            .prefix(&format!(".{file_name}-"))
-            .tempfile_in(&dir_path)
-            .context("failed to create temporary file")?;
-
-        // write out keys
-        for key in ssh_keys {
-            writeln!(temp_file, "{key}").with_context(|| {
-                format!("failed to write to file {:?}", temp_file.path().display())
-            })?;
-        }
+    if let Some(content) = render_ssh_keys(&ssh_keys) {
+        ssh_keys_writer(&user).write(file_path, content.as_bytes())?;
 
-        // sync to disk
-        temp_file
-            .as_file()
-            .sync_all()
-            .with_context(|| format!("failed to sync file {:?}", temp_file.path().display()))?;
-
-        // atomically rename to destination
-        // don't leak temporary file on error
-        temp_file
-            .persist(file_path)
-            .map_err(|e| {
-                e.file.close().ok();
-                e.error
-            })
-            .with_context(|| format!("failed to persist file {:?}", file_path.display()))?;
-
-        // emit journal entry
+        // emit journal entry, even if the file was unchanged, since it
+        // reports that keys are present
         write_ssh_key_journal_entry(logging::Priority::Info, &username, &file_path_str, true);
     } else {
-        // delete the file
-        let deleted = match fs::remove_file(file_path) {
-            Err(ref e) if e.kind() == NotFound => Ok(false),
-            Err(e) => Err(e),
-            Ok(()) => Ok(true),
-        }
-        .with_context(|| format!("failed to remove file {:?}", file_path.display()))?;
-
-        // emit journal entry
-        if deleted {
+        // delete the file, and emit journal entry
+        if util::remove_file(file_path)? {
             write_ssh_key_journal_entry(logging::Priority::Info, &username, &file_path_str, false);
         }
     }
 
-    // sync parent dir to persist updates
-    match File::open(&dir_path) {
-        Ok(dir_file) => dir_file.sync_all(),
-        Err(ref e) if e.kind() == NotFound => Ok(()),
-        Err(e) => Err(e),
-    }
-    .with_context(|| format!("failed to sync '{}'", dir_path.display()))?;
-
     // make clippy happy while fulfilling our interface
     drop(user);
 
@@ -227,94 +215,188 @@ pub trait MetadataProvider {
         Ok(None)
     }
 
-    fn write_attributes(&self, attributes_file_path: String) -> Result<()> {
-        let mut attributes_file = create_file(&attributes_file_path)?;
-        for (k, v) in self.attributes()? {
-            writeln!(&mut attributes_file, "AFTERBURN_{k}={v}").with_context(|| {
-                format!("failed to write attributes to file {attributes_file:?}")
-            })?;
+    /// Return the provider these metadata were recorded from, if they
+    /// were not fetched live.
+    fn recorded_provider(&self) -> Option<&str> {
+        None
+    }
+
+    /// Render the content of the attributes file.
+    ///
+    /// If `platform` is set, normalized attributes for it are included.
+    /// Attributes are then filtered and prefixed according to `policy`.
+    fn render_attributes(
+        &self,
+        format: AttributesFormat,
+        platform: Option<&str>,
+        policy: &AttributesConfig,
+    ) -> Result<String> {
+        let prefix = policy.prefix()?;
+        let mut attrs = self.attributes()?;
+        if let Some(platform) = platform {
+            let normalized = metadata::normalized_attributes(platform, &attrs);
+            attrs.extend(normalized);
         }
+        attributes::render(policy.filter(attrs), format, prefix, metadata::value_type)
+    }
+
+    /// Render the kubelet drop-in, for a node on `platform`.
+    fn render_kubelet_config(&self, platform: &str) -> Result<String> {
+        let identity = NodeIdentity::from_attributes(platform, &self.attributes()?)?;
+        Ok(identity.render())
+    }
+
+    /// Render the authorized keys fragment, with keys filtered according to
+    /// `policy`, or `None` if the fragment should be removed.
+    fn render_ssh_keys(&self, policy: &SshKeysConfig) -> Result<Option<String>> {
+        Ok(render_ssh_keys(&policy.filter_keys(self.ssh_keys()?)))
+    }
+
+    /// Render the hostname, truncated to fit the system maximum length.
+    fn render_hostname(&self) -> Result<Option<String>> {
+        let mut hostname = match self.hostname()? {
+            Some(hostname) => hostname,
+            None => return Ok(None),
+        };
+        if let Some(maxlen) = max_hostname_len()? {
+            if hostname.len() > maxlen {
+                // Value exceeds the system's maximum hostname length.
+                // Truncate hostname to the first dot, or to the maximum
+                // length if necessary.
+                // https://github.com/coreos/afterburn/issues/509
+                slog_scope::info!(
+                    "received hostname {:?} longer than {} characters; truncating",
+                    hostname,
+                    maxlen
+                );
+                hostname.truncate(maxlen);
+                if let Some(idx) = hostname.find('.') {
+                    hostname.truncate(idx);
+                }
+            }
+        }
+        Ok(Some(hostname))
+    }
+
+    /// Render systemd-networkd units, as pairs of file name and content.
+    ///
+    /// This includes `.network` fragments for network interfaces/links and
+    /// `.netdev` fragments for virtual network devices.
+    fn render_network_units(&self) -> Result<Vec<(String, String)>> {
+        let mut units = vec![];
+        for interface in &self.networks()? {
+            units.push((interface.sd_network_unit_name()?, interface.config()));
+        }
+        for device in &self.virtual_network_devices()? {
+            units.push((device.netdev_unit_name(), device.sd_netdev_config()));
+        }
+        Ok(units)
+    }
+
+    /// Render the netplan config, as a pair of file name and content.
+    fn render_netplan_config(&self) -> Result<Option<(String, String)>> {
+        Ok(self
+            .netplan_config()?
+            .map(|config| (NETPLAN_CONFIG_FILE.to_string(), config)))
+    }
+
+    fn write_attributes(
+        &self,
+        attributes_file_path: String,
+        format: AttributesFormat,
+        platform: Option<&str>,
+        policy: &AttributesConfig,
+        writer: &AtomicWrite,
+    ) -> Result<()> {
+        let content = self.render_attributes(format, platform, policy)?;
+        let file_path = Path::new(&attributes_file_path);
+        let outcome = writer
+            .write(file_path, content.as_bytes())
+            .with_context(|| format!("failed to write attributes to file {file_path:?}"))
+            .context(ErrorKind::WriteFailed)?;
+        log_write(file_path, outcome);
+        Ok(())
+    }
+
+    fn write_kubelet_config(
+        &self,
+        kubelet_config_path: String,
+        platform: &str,
+        writer: &AtomicWrite,
+    ) -> Result<()> {
+        let content = self.render_kubelet_config(platform)?;
+        let file_path = Path::new(&kubelet_config_path);
+        let outcome = writer
+            .write(file_path, content.as_bytes())
+            .with_context(|| format!("failed to write kubelet config to file {file_path:?}"))
+            .context(ErrorKind::WriteFailed)?;
+        log_write(file_path, outcome);
         Ok(())
     }
 
-    fn write_ssh_keys(&self, ssh_keys_user: String) -> Result<()> {
-        let ssh_keys = self.ssh_keys()?;
-        let user = uzers::get_user_by_name(&ssh_keys_user)
-            .ok_or_else(|| anyhow!("could not find user with username {:?}", ssh_keys_user))?;
+    fn write_ssh_keys(&self, ssh_keys_user: String, policy: &SshKeysConfig) -> Result<()> {
+        let ssh_keys = policy.filter_keys(self.ssh_keys()?);
+        let user = get_user(&ssh_keys_user)?;
 
-        write_ssh_keys(user, ssh_keys)?;
+        write_ssh_keys(user, ssh_keys).context(ErrorKind::WriteFailed)?;
 
         Ok(())
     }
 
-    fn write_hostname(&self, hostname_file_path: String) -> Result<()> {
-        if let Some(mut hostname) = self.hostname()? {
-            if let Some(maxlen) = max_hostname_len()? {
-                if hostname.len() > maxlen {
-                    // Value exceeds the system's maximum hostname length.
-                    // Truncate hostname to the first dot, or to the maximum
-                    // length if necessary.
-                    // https://github.com/coreos/afterburn/issues/509
-                    slog_scope::info!(
-                        "received hostname {:?} longer than {} characters; truncating",
-                        hostname,
-                        maxlen
-                    );
-                    hostname.truncate(maxlen);
-                    if let Some(idx) = hostname.find('.') {
-                        hostname.truncate(idx);
-                    }
+    fn write_hostname(&self, hostname_file_path: String, writer: &AtomicWrite) -> Result<()> {
+        if let Some(hostname) = self.render_hostname()? {
+            let file_path = Path::new(&hostname_file_path);
+            let outcome = writer
+                .write(file_path, format!("{hostname}\n").as_bytes())
+                .with_context(|| {
+                    format!("failed to write hostname {hostname:?} to file {file_path:?}")
+                })
+                .context(ErrorKind::WriteFailed)?;
+            match outcome {
+                WriteOutcome::Written => {
+                    slog_scope::info!("wrote hostname {} to {}", hostname, hostname_file_path)
                 }
+                WriteOutcome::Unchanged => slog_scope::info!(
+                    "hostname {} unchanged in {}, skipped",
+                    hostname,
+                    hostname_file_path
+                ),
             }
-
-            let mut hostname_file = create_file(&hostname_file_path)?;
-            writeln!(&mut hostname_file, "{hostname}").with_context(|| {
-                format!("failed to write hostname {hostname:?} to file {hostname_file:?}")
-            })?;
-            slog_scope::info!("wrote hostname {} to {}", hostname, hostname_file_path);
         }
         Ok(())
     }
 
-    fn write_network_units(&self, network_units_dir: String) -> Result<()> {
+    fn write_network_units(&self, network_units_dir: String, writer: &AtomicWrite) -> Result<()> {
         let dir_path = Path::new(&network_units_dir);
         fs::create_dir_all(dir_path)
-            .with_context(|| format!("failed to create directory {dir_path:?}"))?;
+            .with_context(|| format!("failed to create directory {dir_path:?}"))
+            .context(ErrorKind::WriteFailed)?;
 
-        // Write `.network` fragments for network interfaces/links.
-        for interface in &self.networks()? {
-            let unit_name = interface.sd_network_unit_name()?;
+        for (unit_name, content) in self.render_network_units()? {
             let file_path = dir_path.join(unit_name);
-            let mut unit_file = File::create(&file_path)
-                .with_context(|| format!("failed to create file {file_path:?}"))?;
-            write!(&mut unit_file, "{}", interface.config()).with_context(|| {
-                format!("failed to write network interface unit file {unit_file:?}")
-            })?;
-        }
-
-        // Write `.netdev` fragments for virtual network devices.
-        for device in &self.virtual_network_devices()? {
-            let file_path = dir_path.join(device.netdev_unit_name());
-            let mut unit_file = File::create(&file_path)
-                .with_context(|| format!("failed to create netdev unit file {file_path:?}"))?;
-            write!(&mut unit_file, "{}", device.sd_netdev_config())
-                .with_context(|| format!("failed to write netdev unit file {unit_file:?}"))?;
+            let outcome = writer
+                .write(&file_path, content.as_bytes())
+                .with_context(|| format!("failed to write network unit file {file_path:?}"))
+                .context(ErrorKind::WriteFailed)?;
+            log_write(&file_path, outcome);
         }
         Ok(())
     }
 
-    fn write_netplan_config(&self, netplan_config_dir: String) -> Result<()> {
+    fn write_netplan_config(&self, netplan_config_dir: String, writer: &AtomicWrite) -> Result<()> {
         let dir_path = Path::new(&netplan_config_dir);
         fs::create_dir_all(dir_path)
-            .with_context(|| format!("failed to create directory {dir_path:?}"))?;
+            .with_context(|| format!("failed to create directory {dir_path:?}"))
+            .context(ErrorKind::WriteFailed)?;
 
         // Write a single afterburn `.yaml` netplan config.
-        if let Some(netplan_config) = &self.netplan_config()? {
-            let file_path = dir_path.join("50-afterburn.yaml");
-            let mut config_file = File::create(&file_path)
-                .with_context(|| format!("failed to create file {file_path:?}"))?;
-            write!(&mut config_file, "{netplan_config}")
-                .with_context(|| format!("failed to write netplan config file {config_file:?}"))?;
+        if let Some((file_name, content)) = self.render_netplan_config()? {
+            let file_path = dir_path.join(file_name);
+            let outcome = writer
+                .write(&file_path, content.as_bytes())
+                .with_context(|| format!("failed to write netplan config file {file_path:?}"))
+                .context(ErrorKind::WriteFailed)?;
+            log_write(&file_path, outcome);
         }
         Ok(())
     }
@@ -335,13 +417,16 @@ mod tests {
 
     // write specified hostname to a file, then read it back
     fn try_write_hostname(hostname: &str) -> String {
-        let mut temp = NamedTempFile::new().unwrap();
+        let temp = NamedTempFile::new().unwrap();
         let provider = HostnameMock(hostname.into());
         provider
-            .write_hostname(temp.path().to_str().unwrap().into())
+            .write_hostname(
+                temp.path().to_str().unwrap().into(),
+                &AtomicWrite::default(),
+            )
             .unwrap();
-        let mut ret = String::new();
-        temp.read_to_string(&mut ret).unwrap();
+        // the file was replaced, so read it again by path
+        let ret = fs::read_to_string(temp.path()).unwrap();
         ret.trim_end().into()
     }
 
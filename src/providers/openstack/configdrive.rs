@@ -12,6 +12,7 @@ use openssh_keys::PublicKey;
 use slog_scope::{error, warn};
 use tempfile::TempDir;
 
+use crate::errors::ErrorKind;
 use crate::network;
 use crate::providers::MetadataProvider;
 
@@ -40,7 +41,6 @@ pub struct MetadataOpenstackJSON {
     /// Instance ID.
     pub uuid: Option<String>,
     /// Availability zone.
-    #[allow(dead_code)]
     pub availability_zone: Option<String>,
     /// Local hostname.
     pub hostname: Option<String>,
@@ -90,14 +90,18 @@ impl OpenstackConfigDrive {
     ///
     /// Metadata file contains a JSON object, corresponding to `MetadataEc2JSON`.
     fn parse_metadata_ec2<T: Read>(input: BufReader<T>) -> Result<MetadataEc2JSON> {
-        serde_json::from_reader(input).context("failed to parse JSON metadata")
+        serde_json::from_reader(input)
+            .context("failed to parse JSON metadata")
+            .context(ErrorKind::MalformedMetadata)
     }
 
     /// Parse metadata attributes
     ///
     /// Metadata file contains a JSON object, corresponding to `MetadataOpenstackJSON`.
     fn parse_metadata_openstack<T: Read>(input: BufReader<T>) -> Result<MetadataOpenstackJSON> {
-        serde_json::from_reader(input).context("failed to parse JSON metadata")
+        serde_json::from_reader(input)
+            .context("failed to parse JSON metadata")
+            .context(ErrorKind::MalformedMetadata)
     }
 
     /// The metadata is stored as key:value pair in ec2/latest/meta-data.json file
@@ -157,6 +161,9 @@ impl MetadataProvider for OpenstackConfigDrive {
         if let Some(uuid) = metadata_openstack.uuid {
             out.insert("OPENSTACK_INSTANCE_UUID".to_string(), uuid);
         }
+        if let Some(availability_zone) = metadata_openstack.availability_zone {
+            out.insert("OPENSTACK_AVAILABILITY_ZONE".to_string(), availability_zone);
+        }
 
         let Some(metadata_ec2) = self.read_metadata_ec2()? else {
             return Ok(out);
@@ -282,4 +289,29 @@ mod tests {
             "b3f43d9c-9198-4cd4-ac9f-bed14960794b"
         );
     }
+
+    #[test]
+    fn test_attributes_normalized_keys() {
+        let drive = tempfile::tempdir().unwrap();
+        for (platform, file) in [("ec2", "meta-data.json"), ("openstack", "meta_data.json")] {
+            let dir = drive.path().join(platform).join("latest");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::copy(
+                Path::new("./tests/fixtures/openstack-config-drive")
+                    .join(platform)
+                    .join(file),
+                dir.join(file),
+            )
+            .unwrap();
+        }
+        let provider = OpenstackConfigDrive {
+            drive_path: drive.path().to_owned(),
+            temp_dir: None,
+        };
+        let attributes = provider.attributes().unwrap();
+        assert_eq!(
+            super::super::NORMALIZED_KEYS.missing_keys(&attributes),
+            Vec::<&str>::new()
+        );
+    }
 }
@@ -80,6 +80,10 @@ fn test_instance_uuid() {
         v.get("OPENSTACK_INSTANCE_UUID"),
         Some(&String::from("99dcf33b-6eb5-4acf-9abb-d81723e0c949"))
     );
+    assert_eq!(
+        v.get("OPENSTACK_AVAILABILITY_ZONE"),
+        Some(&String::from("nova"))
+    );
     server.reset();
     provider.attributes().unwrap_err();
 }
@@ -108,3 +112,42 @@ fn test_instance_uuid_404_ok() {
     server.reset();
     provider.attributes().unwrap_err();
 }
+
+#[test]
+fn test_attributes() {
+    let mut server = mockito::Server::new();
+    let mut provider = OpenstackProviderNetwork::try_new().unwrap();
+    provider.client = provider.client.max_retries(0).mock_base_url(server.url());
+
+    server
+        .mock("GET", "/openstack/2012-08-10/meta_data.json")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body_from_file(
+            "tests/fixtures/openstack-metadata/openstack/2012-08-10/meta_data.json",
+        )
+        .create();
+
+    let endpoints = maplit::btreemap! {
+        "/latest/meta-data/hostname" => "mule",
+        "/latest/meta-data/instance-id" => "i-0000b3c1",
+        "/latest/meta-data/instance-type" => "m1.small",
+        "/latest/meta-data/local-ipv4" => "10.0.151.35",
+        "/latest/meta-data/public-ipv4" => "172.24.4.10",
+    };
+    for (endpoint, body) in endpoints {
+        server
+            .mock("GET", endpoint)
+            .with_status(200)
+            .with_body(body)
+            .create();
+    }
+
+    let v = provider.attributes().unwrap();
+    assert_eq!(v.len(), 7);
+    assert_eq!(
+        v.get("OPENSTACK_INSTANCE_TYPE"),
+        Some(&String::from("m1.small"))
+    );
+    assert_eq!(super::NORMALIZED_KEYS.missing_keys(&v), Vec::<&str>::new());
+}
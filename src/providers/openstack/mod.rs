@@ -14,6 +14,8 @@
 
 //! openstack metadata fetcher
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers;
 use anyhow::Result;
 use configdrive::OpenstackConfigDrive;
@@ -26,6 +28,24 @@ pub mod network;
 #[cfg(test)]
 mod mock_tests;
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("OPENSTACK_INSTANCE_UUID"),
+    instance_type: Some("OPENSTACK_INSTANCE_TYPE"),
+    zone: Some("OPENSTACK_AVAILABILITY_ZONE"),
+    ipv4_private: Some("OPENSTACK_IPV4_LOCAL"),
+    ipv4_public: Some("OPENSTACK_IPV4_PUBLIC"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::KubeletConfig,
+];
+
 /// Read metadata from the config-drive first then fallback to fetch from metadata server.
 ///
 /// Reference: https://github.com/coreos/fedora-coreos-tracker/issues/422
@@ -6,6 +6,7 @@ use anyhow::{anyhow, bail, Context, Result};
 use openssh_keys::PublicKey;
 use serde::Deserialize;
 
+use crate::errors::ErrorKind;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
@@ -17,6 +18,8 @@ const NOVA_URL: &str = "http://169.254.169.254/openstack/2012-08-10/meta_data.js
 pub struct MetadataOpenstackJSON {
     /// Instance ID.
     pub uuid: Option<String>,
+    /// Availability zone.
+    pub availability_zone: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -40,8 +43,9 @@ impl OpenstackProviderNetwork {
             self.client.get(retry::Raw, String::from(NOVA_URL)).send()?;
 
         if let Some(metadata) = metadata {
-            let metadata: MetadataOpenstackJSON =
-                serde_json::from_str(&metadata).context("failed to parse JSON metadata")?;
+            let metadata: MetadataOpenstackJSON = serde_json::from_str(&metadata)
+                .context("failed to parse JSON metadata")
+                .context(ErrorKind::MalformedMetadata)?;
             Ok(metadata)
         } else {
             Ok(MetadataOpenstackJSON::default())
@@ -103,6 +107,9 @@ impl MetadataProvider for OpenstackProviderNetwork {
         if let Some(instance_uuid) = openstack_metadata.uuid {
             out.insert("OPENSTACK_INSTANCE_UUID".to_string(), instance_uuid);
         };
+        if let Some(availability_zone) = openstack_metadata.availability_zone {
+            out.insert("OPENSTACK_AVAILABILITY_ZONE".to_string(), availability_zone);
+        };
         add_value(&mut out, "OPENSTACK_INSTANCE_TYPE", "instance-type")?;
         add_value(&mut out, "OPENSTACK_IPV4_LOCAL", "local-ipv4")?;
         add_value(&mut out, "OPENSTACK_IPV4_PUBLIC", "public-ipv4")?;
@@ -133,6 +133,7 @@ fn test_packet_attributes() {
     let v = provider.attributes().unwrap();
 
     assert_eq!(v, attributes);
+    assert_eq!(packet::NORMALIZED_KEYS.missing_keys(&v), Vec::<&str>::new());
 
     server.reset();
 
@@ -28,6 +28,8 @@ use pnet_base::MacAddr;
 use serde::Deserialize;
 use slog_scope::warn;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::network::{self, Interface, NetworkRoute};
 use crate::providers::MetadataProvider;
 use crate::retry;
@@ -106,7 +108,9 @@ impl PacketProvider {
     pub(crate) fn fetch_content(client: Option<retry::Client>) -> Result<Self> {
         let client = match client {
             Some(c) => c,
-            None => retry::Client::try_new()?,
+            // The metadata endpoint is public, so allow reaching it through
+            // the proxy configured in the environment.
+            None => retry::Client::try_new_with_proxy_policy(retry::ProxyPolicy::Environment)?,
         };
 
         let data: PacketData = client
@@ -346,6 +350,24 @@ impl PacketProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_type: Some("PACKET_PLAN"),
+    ipv4_private: Some("PACKET_IPV4_PRIVATE_0"),
+    ipv4_public: Some("PACKET_IPV4_PUBLIC_0"),
+    ipv6: Some("PACKET_IPV6_PUBLIC_0"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::Networks,
+    Capability::BootCheckin,
+];
+
 impl MetadataProvider for PacketProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         Ok(self.get_attrs().into_iter().collect())
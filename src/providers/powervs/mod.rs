@@ -21,6 +21,9 @@ use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
+use crate::attributes::Normalized;
+use crate::errors::ErrorKind;
+use crate::metadata::Capability;
 use crate::network;
 use crate::providers::MetadataProvider;
 
@@ -98,7 +101,9 @@ impl PowerVSProvider {
     ///
     /// Metadata file contains a JSON object, corresponding to `MetaDataJSON`.
     fn parse_metadata<T: Read>(input: BufReader<T>) -> Result<MetaDataJSON> {
-        serde_json::from_reader(input).context("failed to parse JSON metadata")
+        serde_json::from_reader(input)
+            .context("failed to parse JSON metadata")
+            .context(ErrorKind::MalformedMetadata)
     }
 
     /// Extract supported metadata values and convert to Afterburn attributes.
@@ -135,6 +140,19 @@ impl PowerVSProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("POWERVS_INSTANCE_ID"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+];
+
 impl MetadataProvider for PowerVSProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let metadata = self.read_metadata()?;
@@ -213,6 +231,7 @@ mod tests {
             attrs.get("POWERVS_LOCAL_HOSTNAME"),
             Some(&"test_instance-powervs".to_string())
         );
+        assert_eq!(NORMALIZED_KEYS.missing_keys(&attrs), Vec::<&str>::new());
     }
 
     #[test]
@@ -1,5 +1,5 @@
 use super::ProxmoxVECloudConfig;
-use crate::{network, providers::MetadataProvider};
+use crate::{errors::ErrorKind, network, providers::MetadataProvider};
 use anyhow::{Context, Result};
 use openssh_keys::PublicKey;
 use slog_scope::error;
@@ -36,7 +36,8 @@ impl ProxmoxVEConfigDrive {
             .context("failed to create temporary directory")?;
 
         let device_path = Self::find_cidata_device()
-            .ok_or_else(|| anyhow::anyhow!("could not find cidata device"))?;
+            .ok_or_else(|| anyhow::anyhow!("could not find cidata device"))
+            .context(ErrorKind::ConfigDriveNotFound)?;
 
         crate::util::mount_ro(Path::new(&device_path), mount_dir.path(), TARGET_FS, 3)?;
 
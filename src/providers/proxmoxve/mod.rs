@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers;
 use crate::providers::noop::NoopProvider;
 use anyhow::Result;
@@ -26,6 +28,22 @@ pub use cloudconfig::*;
 #[cfg(test)]
 mod tests;
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("PROXMOXVE_INSTANCE_ID"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::Networks,
+    Capability::NetplanConfig,
+    Capability::RdNetworkKargs,
+];
+
 pub fn try_config_drive_else_leave() -> Result<Box<dyn providers::MetadataProvider>> {
     match ProxmoxVEConfigDrive::try_new() {
         Ok(config_drive) => Ok(Box::new(config_drive)),
@@ -27,6 +27,10 @@ fn test_attributes() {
         attributes["PROXMOXVE_IPV6"],
         "2001:db8:85a3::8a2e:370:0".to_string()
     );
+    assert_eq!(
+        super::NORMALIZED_KEYS.missing_keys(&attributes),
+        Vec::<&str>::new()
+    );
 }
 
 #[test]
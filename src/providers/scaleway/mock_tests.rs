@@ -44,6 +44,10 @@ fn test_attributes() {
     let got = provider.attributes().unwrap();
 
     assert_eq!(got, want);
+    assert_eq!(
+        super::NORMALIZED_KEYS.missing_keys(&got),
+        Vec::<&str>::new()
+    );
 
     server.reset();
 }
@@ -29,6 +29,8 @@ use anyhow::{anyhow, Result};
 use openssh_keys::PublicKey;
 use serde::Deserialize;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
@@ -125,6 +127,25 @@ impl ScalewayProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("SCALEWAY_INSTANCE_ID"),
+    instance_type: Some("SCALEWAY_INSTANCE_TYPE"),
+    zone: Some("SCALEWAY_ZONE_ID"),
+    ipv4_private: Some("SCALEWAY_IPV4_PRIVATE"),
+    ipv4_public: Some("SCALEWAY_IPV4_PUBLIC"),
+    ipv6: Some("SCALEWAY_IPV6_PUBLIC"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+    Capability::BootCheckin,
+];
+
 impl MetadataProvider for ScalewayProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let attrs = self.parse_attrs()?;
@@ -3,6 +3,7 @@
 //! This uses the guest->host backdoor protocol for introspection.
 
 use super::VmwareProvider;
+use crate::redact::Secret;
 use anyhow::{bail, Context, Result};
 use base64::{engine::general_purpose, Engine as _};
 use libflate::gzip::Decoder;
@@ -51,7 +52,7 @@ impl VmwareProvider {
 
         let provider = Self {
             guestinfo_net_kargs,
-            guestinfo_metadata,
+            guestinfo_metadata: Secret(guestinfo_metadata),
         };
 
         slog_scope::trace!("cached vmware provider: {:?}", provider);
@@ -68,7 +69,7 @@ impl VmwareProvider {
     }
 
     pub fn parse_netplan_config(&self) -> Result<Option<String>> {
-        if let Some(metadata) = &self.guestinfo_metadata {
+        if let Some(metadata) = &self.guestinfo_metadata.0 {
             // We need to parse the netplan config to remove the non-Netplan keys.
             // The data can either be JSON or YAML, but since JSON is a subset of
             // YAML we don't need to try serde_json::from_str here.
@@ -90,7 +91,7 @@ impl VmwareProvider {
     pub fn new_from_metadata(metadata: String) -> Result<Self> {
         Ok(Self {
             guestinfo_net_kargs: None,
-            guestinfo_metadata: Some(metadata),
+            guestinfo_metadata: Secret(Some(metadata)),
         })
     }
 }
@@ -4,15 +4,17 @@ use std::collections::HashMap;
 
 use anyhow::Result;
 
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
+use crate::redact::Secret;
 
 /// VMware provider.
 #[derive(Clone, Debug)]
 pub struct VmwareProvider {
     /// External network kargs for initrd.
     guestinfo_net_kargs: Option<String>,
-    /// Cloud-Init metadata for netplan YAML
-    guestinfo_metadata: Option<String>,
+    /// Cloud-Init metadata for netplan YAML, which may hold credentials
+    guestinfo_metadata: Secret<Option<String>>,
 }
 
 // Architecture-specific implementation.
@@ -24,6 +26,10 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] =
+    &[Capability::NetplanConfig, Capability::RdNetworkKargs];
+
 impl MetadataProvider for VmwareProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         Ok(HashMap::new())
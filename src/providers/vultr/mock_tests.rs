@@ -100,6 +100,7 @@ fn test_attributes() {
 
     let v = provider.attributes().unwrap();
     assert_eq!(v, attributes);
+    assert_eq!(vultr::NORMALIZED_KEYS.missing_keys(&v), Vec::<&str>::new());
 
     server.reset();
     provider.attributes().unwrap_err();
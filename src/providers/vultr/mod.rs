@@ -21,6 +21,8 @@ use openssh_keys::PublicKey;
 use slog_scope::error;
 use std::collections::HashMap;
 
+use crate::attributes::Normalized;
+use crate::metadata::Capability;
 use crate::providers::MetadataProvider;
 use crate::retry;
 
@@ -43,20 +45,26 @@ impl VultrProvider {
         format!("http://169.254.169.254/v1/{name}")
     }
 
-    fn fetch_attribute(
+    /// Fetch metadata attributes from their specific endpoints.
+    ///
+    /// Each `(key, endpoint)` pair is fetched concurrently, and content
+    /// (if any) is stored into the provided `map`.
+    fn fetch_attributes(
         &self,
         map: &mut HashMap<String, String>,
-        key: &str,
-        endpoint: &str,
+        attributes: &[(&str, &str)],
     ) -> Result<()> {
-        let content: Option<String> = self
-            .client
-            .get(retry::Raw, Self::endpoint_for(endpoint))
-            .send()?;
-
-        if let Some(value) = content {
-            if !value.is_empty() {
-                map.insert(key.to_string(), value);
+        let urls = attributes
+            .iter()
+            .map(|(_, endpoint)| Self::endpoint_for(endpoint))
+            .collect();
+        let contents: Vec<Option<String>> = self.client.send_batch(retry::Raw, urls)?;
+
+        for ((key, _), content) in attributes.iter().zip(contents) {
+            if let Some(value) = content {
+                if !value.is_empty() {
+                    map.insert(key.to_string(), value);
+                }
             }
         }
 
@@ -94,13 +102,32 @@ impl VultrProvider {
     }
 }
 
+/// Attributes mapped to normalized ones.
+pub(crate) const NORMALIZED_KEYS: Normalized<&str> = Normalized {
+    instance_id: Some("VULTR_INSTANCE_ID"),
+    region: Some("VULTR_REGION_CODE"),
+    ..Normalized::NONE
+};
+
+/// Supported metadata features.
+pub(crate) const CAPABILITIES: &[Capability] = &[
+    Capability::Attributes,
+    Capability::Hostname,
+    Capability::SshKeys,
+];
+
 impl MetadataProvider for VultrProvider {
     fn attributes(&self) -> Result<HashMap<String, String>> {
         let mut out = HashMap::with_capacity(3);
 
-        self.fetch_attribute(&mut out, "VULTR_HOSTNAME", "hostname")?;
-        self.fetch_attribute(&mut out, "VULTR_INSTANCE_ID", "instanceid")?;
-        self.fetch_attribute(&mut out, "VULTR_REGION_CODE", "region/regioncode")?;
+        self.fetch_attributes(
+            &mut out,
+            &[
+                ("VULTR_HOSTNAME", "hostname"),
+                ("VULTR_INSTANCE_ID", "instanceid"),
+                ("VULTR_REGION_CODE", "region/regioncode"),
+            ],
+        )?;
 
         Ok(out)
     }
@@ -21,13 +21,20 @@
 
 use std::borrow::Cow;
 use std::io::Read;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{anyhow, Context, Result};
-use reqwest::{self, blocking, header, Method};
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{self, blocking, header, Method, StatusCode};
 use slog_scope::info;
 
-use crate::retry::Retry;
+use crate::errors::ErrorKind;
+use crate::redact;
+use crate::retry::tls::{self, RedirectRefused, TlsSettings};
+use crate::retry::token::TokenCache;
+use crate::retry::{ProxyPolicy, Retry, SpkiPinMismatch, TokenSource, Transport, Verdict};
 
 use crate::retry::raw_deserializer;
 
@@ -104,32 +111,97 @@ impl Deserializer for Raw {
     }
 }
 
+/// Default maximum number of requests in flight for a batch.
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+
+/// Process-wide default timeout for each request.
+static REQUEST_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Set the process-wide default timeout for each request.
+///
+/// This can only be set once, before any client is built.
+pub fn set_request_timeout(timeout: Duration) -> Result<()> {
+    REQUEST_TIMEOUT
+        .set(timeout)
+        .map_err(|_| anyhow!("request timeout already set"))
+}
+
+/// Process-wide override for the base URL of metadata endpoints.
+static BASE_URL_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+/// Override scheme, host and port of all metadata endpoints.
+///
+/// The override is global: endpoints on different hosts, e.g. the Azure
+/// WireServer and instance metadata service, are all redirected to the same
+/// base URL. This can only be set once, before any client is built.
+pub fn set_base_url_override(base_url: &str) -> Result<()> {
+    let url = reqwest::Url::parse(base_url)
+        .with_context(|| format!("failed to parse base URL '{base_url}'"))?;
+    if url.host().is_none() {
+        bail!("base URL '{}' doesn't have a host", base_url);
+    }
+    BASE_URL_OVERRIDE
+        .set(base_url.to_string())
+        .map_err(|_| anyhow!("base URL override already set"))
+}
+
+/// Return the process-wide override for the base URL of metadata endpoints.
+#[cfg_attr(test, allow(dead_code))]
+pub fn base_url_override() -> Option<&'static str> {
+    BASE_URL_OVERRIDE.get().map(String::as_str)
+}
+
 #[derive(Debug, Clone)]
 pub struct Client {
-    client: blocking::Client,
+    transport: Arc<dyn Transport>,
     headers: header::HeaderMap,
     retry: Retry,
     return_on_404: bool,
-    #[cfg(test)]
-    mock_base_url: Option<String>,
+    base_url: Option<String>,
+    batch_concurrency: usize,
+    request_timeout: Option<Duration>,
+    token: Option<Arc<TokenCache>>,
+    tls: Option<&'static TlsSettings>,
 }
 
 impl Client {
+    /// Build a client which never uses an HTTP proxy.
     pub fn try_new() -> Result<Self> {
-        let client = blocking::Client::builder()
-            .build()
-            .context("failed to initialize client")?;
+        Self::try_new_with_proxy_policy(ProxyPolicy::Never)
+    }
+
+    /// Build a client using HTTP proxies according to `policy`.
+    pub fn try_new_with_proxy_policy(policy: ProxyPolicy) -> Result<Self> {
+        Self::build(policy, tls::tls_settings())
+    }
+
+    /// Build a client with TLS settings other than the process-wide ones.
+    #[cfg(test)]
+    pub(crate) fn try_new_with_tls_settings(tls: &'static TlsSettings) -> Result<Self> {
+        Self::build(ProxyPolicy::Never, Some(tls))
+    }
+
+    fn build(policy: ProxyPolicy, tls: Option<&'static TlsSettings>) -> Result<Self> {
+        let mut builder = policy.apply(blocking::Client::builder());
+        if let Some(tls) = tls {
+            builder = tls.apply(builder).context("failed to apply TLS settings")?;
+        }
+        let client = builder.build().context("failed to initialize client")?;
         Ok(Client {
-            client,
+            transport: Arc::new(client),
             headers: header::HeaderMap::new(),
-            retry: Retry::new(),
+            retry: Retry::new().classifier(classify),
             return_on_404: false,
-            #[cfg(test)]
-            mock_base_url: None,
+            base_url: BASE_URL_OVERRIDE.get().cloned(),
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            request_timeout: REQUEST_TIMEOUT.get().copied(),
+            token: None,
+            tls,
         })
     }
 
     pub fn header(mut self, k: header::HeaderName, v: header::HeaderValue) -> Self {
+        let v = redact::header_value(&k, v);
         self.headers.append(k, v);
         self
     }
@@ -161,9 +233,46 @@ impl Client {
         self
     }
 
+    /// Maximum number of requests in flight when sending a batch.
+    ///
+    /// A value of zero or one dispatches batch requests sequentially.
+    #[allow(dead_code)]
+    pub fn batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.batch_concurrency = batch_concurrency;
+        self
+    }
+
+    /// Timeout for each request attempt.
+    ///
+    /// This is further capped by the process-wide deadline, if any.
+    #[allow(dead_code)]
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Send a session token from `source` with each request.
+    ///
+    /// The token is fetched on first use with a copy of this client, and
+    /// fetched again when it expires or is rejected with a 401 response.
+    /// It is shared by all clones of the returned client.
+    pub fn token_source(mut self, source: impl TokenSource + 'static) -> Self {
+        self.token = None;
+        let cache = TokenCache::new(Box::new(source), self.clone());
+        self.token = Some(Arc::new(cache));
+        self
+    }
+
+    /// Send requests through `transport` instead of the default HTTP client.
+    #[cfg(test)]
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
     #[cfg(test)]
     pub fn mock_base_url(mut self, base_url: String) -> Self {
-        self.mock_base_url = Some(base_url);
+        self.base_url = Some(base_url);
         self
     }
 
@@ -175,15 +284,71 @@ impl Client {
             url,
             body: None,
             d,
-            client: self.client.clone(),
+            transport: self.transport.clone(),
             headers: self.headers.clone(),
             retry: self.retry.clone(),
             return_on_404: self.return_on_404,
-            #[cfg(test)]
-            mock_base_url: self.mock_base_url.clone(),
+            base_url: self.base_url.clone(),
+            request_timeout: self.request_timeout,
+            token: self.token.clone(),
+            tls: self.tls,
         }
     }
 
+    /// Send GET requests for a batch of URLs, concurrently.
+    ///
+    /// Each request is retried on its own, as with `send`. Results are
+    /// returned in the same order as `urls`. On failure, pending requests
+    /// are not dispatched, and the error of the first failed URL is returned.
+    pub fn send_batch<D, T>(&self, d: D, urls: Vec<String>) -> Result<Vec<Option<T>>>
+    where
+        D: Deserializer + Clone + Sync,
+        T: for<'de> serde::Deserialize<'de> + Send,
+    {
+        let workers = self.batch_concurrency.clamp(1, urls.len().max(1));
+        if workers == 1 {
+            return urls
+                .into_iter()
+                .map(|url| self.get(d.clone(), url).send())
+                .collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results: Mutex<Vec<Option<Result<Option<T>>>>> =
+            Mutex::new(urls.iter().map(|_| None).collect());
+        // Log scopes are per-thread; carry the current one over to workers.
+        let logger = slog_scope::logger();
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    slog_scope::scope(&logger, || {
+                        while !failed.load(Ordering::Relaxed) {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(url) = urls.get(index) else {
+                                break;
+                            };
+                            let res = self.get(d.clone(), url.clone()).send();
+                            if res.is_err() {
+                                failed.store(true, Ordering::Relaxed);
+                            }
+                            results.lock().unwrap()[index] = Some(res);
+                        }
+                    })
+                });
+            }
+        });
+
+        // Requests are dispatched in order, so any skipped one comes after
+        // a failure.
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map_while(|res| res)
+            .collect::<Result<Vec<_>>>()
+    }
+
     pub fn patch<D>(&self, d: D, url: String, body: Option<Cow<str>>) -> RequestBuilder<D>
     where
         D: Deserializer,
@@ -192,12 +357,14 @@ impl Client {
             url,
             body: body.map(Cow::into_owned),
             d,
-            client: self.client.clone(),
+            transport: self.transport.clone(),
             headers: self.headers.clone(),
             retry: self.retry.clone(),
             return_on_404: self.return_on_404,
-            #[cfg(test)]
-            mock_base_url: self.mock_base_url.clone(),
+            base_url: self.base_url.clone(),
+            request_timeout: self.request_timeout,
+            token: self.token.clone(),
+            tls: self.tls,
         }
     }
 
@@ -209,12 +376,14 @@ impl Client {
             url,
             body: body.map(Cow::into_owned),
             d,
-            client: self.client.clone(),
+            transport: self.transport.clone(),
             headers: self.headers.clone(),
             retry: self.retry.clone(),
             return_on_404: self.return_on_404,
-            #[cfg(test)]
-            mock_base_url: self.mock_base_url.clone(),
+            base_url: self.base_url.clone(),
+            request_timeout: self.request_timeout,
+            token: self.token.clone(),
+            tls: self.tls,
         }
     }
 
@@ -226,12 +395,14 @@ impl Client {
             url,
             body: body.map(Cow::into_owned),
             d,
-            client: self.client.clone(),
+            transport: self.transport.clone(),
             headers: self.headers.clone(),
             retry: self.retry.clone(),
             return_on_404: self.return_on_404,
-            #[cfg(test)]
-            mock_base_url: self.mock_base_url.clone(),
+            base_url: self.base_url.clone(),
+            request_timeout: self.request_timeout,
+            token: self.token.clone(),
+            tls: self.tls,
         }
     }
 }
@@ -243,12 +414,14 @@ where
     url: String,
     body: Option<String>,
     d: D,
-    client: blocking::Client,
+    transport: Arc<dyn Transport>,
     headers: header::HeaderMap,
     retry: Retry,
     return_on_404: bool,
-    #[cfg(test)]
-    mock_base_url: Option<String>,
+    base_url: Option<String>,
+    request_timeout: Option<Duration>,
+    token: Option<Arc<TokenCache>>,
+    tls: Option<&'static TlsSettings>,
 }
 
 impl<D> RequestBuilder<D>
@@ -256,6 +429,7 @@ where
     D: Deserializer,
 {
     pub fn header(mut self, k: header::HeaderName, v: header::HeaderValue) -> Self {
+        let v = redact::header_value(&k, v);
         self.headers.append(k, v);
         self
     }
@@ -268,8 +442,13 @@ where
         let mut req = blocking::Request::new(Method::GET, url);
         req.headers_mut().extend(self.headers.clone());
 
-        self.retry.clone().retry(|attempt| {
-            info!("Fetching {}: Attempt #{}", req.url(), attempt + 1);
+        self.retry.clone().retry_request(|attempt| {
+            let logged_url = redact::url(req.url());
+            info!(
+                "Fetching {}: Attempt #{}", logged_url, attempt + 1;
+                "url" => logged_url.as_str(),
+                "attempt" => attempt + 1
+            );
             self.dispatch_request(&req)
         })
     }
@@ -277,7 +456,7 @@ where
     pub fn dispatch_patch(self) -> Result<reqwest::StatusCode> {
         let url = self.parse_url()?;
 
-        self.retry.clone().retry(|attempt| {
+        self.retry.clone().retry_request(|attempt| {
             let mut builder = blocking::Client::new()
                 .patch(url.clone())
                 .headers(self.headers.clone())
@@ -285,18 +464,28 @@ where
             if let Some(ref content) = self.body {
                 builder = builder.body(content.clone());
             };
+            let timeout = self.timeout();
+            if let Some(timeout) = timeout {
+                builder = builder.timeout(timeout);
+            }
             let req = builder.build().context("failed to build PATCH request")?;
 
-            info!("Patching {}: Attempt #{}", req.url(), attempt + 1);
-            let status = self
-                .client
-                .execute(req)
-                .context("failed to PATCH request")?
-                .status();
+            let logged_url = redact::url(req.url());
+            info!(
+                "Patching {}: Attempt #{}", logged_url, attempt + 1;
+                "url" => logged_url.as_str(),
+                "attempt" => attempt + 1
+            );
+            let response = self
+                .execute(&req, timeout)?
+                .map_err(|e| transport_error(e, timeout))
+                .context("failed to PATCH request")
+                .context(ErrorKind::MetadataUnreachable)?;
+            let status = response.status();
             if status.is_success() {
                 Ok(status)
             } else {
-                Err(anyhow!("PATCH failed: {}", status))
+                Err(StatusError::new("PATCH failed", &response).into_error())
             }
         })
     }
@@ -307,7 +496,7 @@ where
     {
         let url = self.parse_url()?;
 
-        self.retry.clone().retry(|attempt| {
+        self.retry.clone().retry_request(|attempt| {
             let mut builder = blocking::Client::new()
                 .put(url.clone())
                 .headers(self.headers.clone())
@@ -315,18 +504,32 @@ where
             if let Some(ref content) = self.body {
                 builder = builder.body(content.clone());
             };
+            let timeout = self.timeout();
+            if let Some(timeout) = timeout {
+                builder = builder.timeout(timeout);
+            }
             let req = builder.build().context("failed to build PUT request")?;
 
-            info!("Putting {}: Attempt #{}", req.url(), attempt + 1);
-            let response = self.client.execute(req).context("failed to PUT request")?;
+            let logged_url = redact::url(req.url());
+            info!(
+                "Putting {}: Attempt #{}", logged_url, attempt + 1;
+                "url" => logged_url.as_str(),
+                "attempt" => attempt + 1
+            );
+            let response = self
+                .execute(&req, timeout)?
+                .map_err(|e| transport_error(e, timeout))
+                .context("failed to PUT request")
+                .context(ErrorKind::MetadataUnreachable)?;
             let status = response.status();
             if status.is_success() {
                 self.d
                     .deserialize(response)
                     .map(Some)
                     .context("failed to deserialize data")
+                    .context(ErrorKind::MalformedMetadata)
             } else {
-                Err(anyhow!("PUT failed: {}", status))
+                Err(StatusError::new("PUT failed", &response).into_error())
             }
         })
     }
@@ -334,7 +537,7 @@ where
     pub fn dispatch_post(self) -> Result<reqwest::StatusCode> {
         let url = self.parse_url()?;
 
-        self.retry.clone().retry(|attempt| {
+        self.retry.clone().retry_request(|attempt| {
             let mut builder = blocking::Client::new()
                 .post(url.clone())
                 .headers(self.headers.clone())
@@ -342,18 +545,28 @@ where
             if let Some(ref content) = self.body {
                 builder = builder.body(content.clone());
             };
+            let timeout = self.timeout();
+            if let Some(timeout) = timeout {
+                builder = builder.timeout(timeout);
+            }
             let req = builder.build().context("failed to build POST request")?;
 
-            info!("Posting {}: Attempt #{}", req.url(), attempt + 1);
-            let status = self
-                .client
-                .execute(req)
-                .context("failed to POST request")?
-                .status();
+            let logged_url = redact::url(req.url());
+            info!(
+                "Posting {}: Attempt #{}", logged_url, attempt + 1;
+                "url" => logged_url.as_str(),
+                "attempt" => attempt + 1
+            );
+            let response = self
+                .execute(&req, timeout)?
+                .map_err(|e| transport_error(e, timeout))
+                .context("failed to POST request")
+                .context(ErrorKind::MetadataUnreachable)?;
+            let status = response.status();
             if status.is_success() {
                 Ok(status)
             } else {
-                Err(anyhow!("POST failed: {}", status))
+                Err(StatusError::new("POST failed", &response).into_error())
             }
         })
     }
@@ -362,57 +575,459 @@ where
     where
         T: for<'de> serde::Deserialize<'de>,
     {
-        match self.client.execute(clone_request(req)) {
+        let timeout = self.timeout();
+        let logged_url = redact::url(req.url());
+        match self.execute(req, timeout)? {
             Ok(resp) => match (resp.status(), self.return_on_404) {
                 (reqwest::StatusCode::OK, _) => {
-                    info!("Fetch successful");
+                    info!("Fetch successful"; "url" => logged_url.as_str());
                     self.d
                         .deserialize(resp)
                         .map(Some)
                         .context("failed to deserialize data")
+                        .context(ErrorKind::MalformedMetadata)
                 }
                 (reqwest::StatusCode::NOT_FOUND, true) => {
-                    info!("Fetch failed with 404: resource not found");
+                    info!("Fetch failed with 404: resource not found"; "url" => logged_url.as_str());
                     Ok(None)
                 }
                 (s, _) => {
-                    info!("Failed to fetch: {}", s);
-                    Err(anyhow!("failed to fetch: {}", s))
+                    info!("Failed to fetch: {}", s; "url" => logged_url.as_str(), "status" => s.as_u16());
+                    Err(StatusError::new("failed to fetch", &resp).into_error())
                 }
             },
             Err(e) => {
-                info!("Failed to fetch: {}", e);
-                Err(anyhow!(e).context("failed to fetch"))
+                let e = redact::reqwest_error(e);
+                info!("Failed to fetch: {}", e; "url" => logged_url.as_str());
+                Err(transport_error(e, timeout)
+                    .context("failed to fetch")
+                    .context(ErrorKind::MetadataUnreachable))
+            }
+        }
+    }
+
+    /// Execute a request attempt, with the session token if any.
+    ///
+    /// If the token is rejected with a 401 response, a new one is fetched
+    /// and the request sent once more. Failures to fetch the token, or to
+    /// connect because of pinned public keys, are returned as the outer
+    /// error.
+    fn execute(
+        &self,
+        req: &blocking::Request,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Result<blocking::Response>> {
+        let send = |token: Option<(header::HeaderName, header::HeaderValue)>| {
+            let mut attempt = clone_request(req);
+            *attempt.timeout_mut() = timeout;
+            if let Some((name, value)) = token {
+                attempt.headers_mut().insert(name, value);
+            }
+            let res = self.transport.execute(attempt);
+            if let Some(failure) = res.as_ref().err().and_then(tls::pinning_failure) {
+                return Err(failure);
             }
+            Ok(res)
+        };
+
+        let Some(cache) = &self.token else {
+            return send(None);
+        };
+        let res = send(
+            cache
+                .get()
+                .context(TokenFetchFailed("failed to fetch session token"))?,
+        )?;
+        match res {
+            Ok(resp) if resp.status() == StatusCode::UNAUTHORIZED => {
+                info!("Session token rejected, refreshing"; "url" => redact::url(req.url()).as_str());
+                cache.invalidate();
+                send(
+                    cache
+                        .get()
+                        .context(TokenFetchFailed("failed to refresh session token"))?,
+                )
+            }
+            res => Ok(res),
+        }
+    }
+
+    /// Timeout for the next request attempt.
+    ///
+    /// This is the per-request timeout, capped by the time left before the
+    /// process-wide deadline.
+    fn timeout(&self) -> Option<Duration> {
+        let remaining = super::deadline().map(|d| d.remaining());
+        match (self.request_timeout, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
         }
     }
 
     fn parse_url(&self) -> Result<reqwest::Url> {
-        #[allow(unused_mut)]
         let mut url = reqwest::Url::parse(self.url.as_str()).context("failed to parse uri")?;
-        #[cfg(test)]
-        if let Some(mock_base_url) = &self.mock_base_url {
-            let base_url =
-                reqwest::Url::parse(mock_base_url).context("failed to parse mock base URL")?;
+        if let Some(base_url) = &self.base_url {
+            let base_url = reqwest::Url::parse(base_url).context("failed to parse base URL")?;
             url.set_scheme(base_url.scheme())
                 .map_err(|_| anyhow!("failed to update URL scheme"))?;
             let host = base_url
                 .host()
-                .context("mock base URL doesn't have a host")?
+                .context("base URL doesn't have a host")?
                 .to_string();
             url.set_host(Some(&host))
                 .context("failed to update URL host")?;
             url.set_port(base_url.port())
                 .map_err(|_| anyhow!("failed to update URL port"))?;
         }
+        if self.tls.is_some_and(TlsSettings::pinned) && url.scheme() != "https" {
+            return Err(anyhow!(
+                "refusing non-HTTPS request to {} with pinned public keys",
+                redact::url(&url)
+            ))
+            .context(ErrorKind::InvalidConfig);
+        }
         Ok(url)
     }
 }
 
+/// Unsuccessful HTTP response status.
+#[derive(Debug)]
+pub struct StatusError {
+    context: &'static str,
+    status: reqwest::StatusCode,
+    retry_after: Option<Duration>,
+}
+
+impl StatusError {
+    fn new(context: &'static str, response: &blocking::Response) -> Self {
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        StatusError {
+            context,
+            status: response.status(),
+            retry_after,
+        }
+    }
+
+    /// Convert into an error, classified as the metadata service failing.
+    fn into_error(self) -> anyhow::Error {
+        anyhow::Error::new(self).context(ErrorKind::MetadataUnreachable)
+    }
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.context, self.status)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Failure to fetch a session token.
+///
+/// The token is fetched with its own retries, so a request failing on it is
+/// not retried again.
+#[derive(Debug)]
+struct TokenFetchFailed(&'static str);
+
+impl std::fmt::Display for TokenFetchFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Parse a `Retry-After` header value, either in seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Classify failed requests for retrying.
+///
+/// Mismatching pinned public keys, refused redirects, failures to fetch a
+/// session token and client errors are fatal, except for 404 (resources appearing later), 408
+/// and 429. A `Retry-After` delay is
+/// honoured on 429 and 503.
+fn classify(err: &anyhow::Error) -> Verdict {
+    if err.downcast_ref::<SpkiPinMismatch>().is_some()
+        || err.downcast_ref::<RedirectRefused>().is_some()
+        || err.downcast_ref::<TokenFetchFailed>().is_some()
+    {
+        return Verdict::Fatal;
+    }
+    let Some(e) = err.downcast_ref::<StatusError>() else {
+        return Verdict::Retry;
+    };
+    match e.status {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+            e.retry_after.map_or(Verdict::Retry, Verdict::RetryAfter)
+        }
+        StatusCode::NOT_FOUND | StatusCode::REQUEST_TIMEOUT => Verdict::Retry,
+        s if s.is_client_error() => Verdict::Fatal,
+        _ => Verdict::Retry,
+    }
+}
+
+/// Convert a transport error, stating the timeout if it was hit.
+fn transport_error(e: reqwest::Error, timeout: Option<Duration>) -> anyhow::Error {
+    let e = redact::reqwest_error(e);
+    match timeout {
+        Some(timeout) if e.is_timeout() => anyhow!(e).context(format!(
+            "request timed out after {}s",
+            timeout.as_secs_f64()
+        )),
+        _ => anyhow!(e),
+    }
+}
+
 /// Reqwests Request struct doesn't implement `Clone`,
 /// so we have to do it here.
 fn clone_request(req: &blocking::Request) -> blocking::Request {
     let mut newreq = blocking::Request::new(req.method().clone(), req.url().clone());
     newreq.headers_mut().extend(req.headers().clone());
+    if let Some(body) = req.body().and_then(|b| b.as_bytes()) {
+        *newreq.body_mut() = Some(body.to_vec().into());
+    }
     newreq
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_batch() {
+        let mut server = mockito::Server::new();
+        let client = Client::try_new()
+            .unwrap()
+            .max_retries(0)
+            .return_on_404(true)
+            .mock_base_url(server.url());
+        let urls: Vec<_> = (0..10)
+            .map(|n| format!("http://192.0.2.1/key-{n}"))
+            .collect();
+
+        for n in 0..10 {
+            let mock = server.mock("GET", format!("/key-{n}").as_str());
+            if n == 3 {
+                mock.with_status(404).create();
+            } else {
+                mock.with_status(200)
+                    .with_body(format!("value-{n}"))
+                    .create();
+            }
+        }
+        let values: Vec<Option<String>> = client.send_batch(Raw, urls.clone()).unwrap();
+        let expected: Vec<_> = (0..10)
+            .map(|n| (n != 3).then(|| format!("value-{n}")))
+            .collect();
+        assert_eq!(values, expected);
+
+        // sequential dispatch returns the same
+        let values: Vec<Option<String>> = client
+            .clone()
+            .batch_concurrency(1)
+            .send_batch(Raw, urls.clone())
+            .unwrap();
+        assert_eq!(values, expected);
+
+        server.mock("GET", "/key-5").with_status(503).create();
+        client.send_batch::<_, String>(Raw, urls).unwrap_err();
+
+        let values: Vec<Option<String>> = client.send_batch(Raw, vec![]).unwrap();
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn test_classify() {
+        let mut server = mockito::Server::new();
+        let client = Client::try_new()
+            .unwrap()
+            .initial_backoff(Duration::from_millis(10))
+            .max_retries(3)
+            .mock_base_url(server.url());
+        let get = |path: &str| {
+            client
+                .get(Raw, format!("http://192.0.2.1{path}"))
+                .send::<String>()
+        };
+
+        // client errors are not retried
+        let mock = server
+            .mock("GET", "/forbidden")
+            .with_status(403)
+            .expect(1)
+            .create();
+        let err = get("/forbidden").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "metadata service unreachable: failed to fetch: 403 Forbidden"
+        );
+        mock.assert();
+
+        // except for throttling
+        let mock = server
+            .mock("GET", "/throttled")
+            .with_status(429)
+            .with_header("Retry-After", "0")
+            .expect(4)
+            .create();
+        get("/throttled").unwrap_err();
+        mock.assert();
+
+        // server errors are retried
+        let mock = server
+            .mock("GET", "/unavailable")
+            .with_status(500)
+            .expect(4)
+            .create();
+        get("/unavailable").unwrap_err();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_token_fetch_failure() {
+        #[derive(Debug)]
+        struct Source;
+
+        impl TokenSource for Source {
+            fn header_name(&self) -> header::HeaderName {
+                header::HeaderName::from_static("x-token")
+            }
+
+            fn fetch(&self, client: &Client) -> Result<crate::retry::Token> {
+                let token: String = client
+                    .put(Raw, "http://192.0.2.1/token".to_string(), None)
+                    .dispatch_put()?
+                    .context("missing token")?;
+                Ok(crate::retry::Token::new(
+                    header::HeaderValue::from_str(&token)?,
+                    Duration::from_secs(60),
+                ))
+            }
+        }
+
+        let mut server = mockito::Server::new();
+        let client = Client::try_new()
+            .unwrap()
+            .initial_backoff(Duration::from_millis(10))
+            .max_retries(3)
+            .mock_base_url(server.url())
+            .token_source(Source);
+
+        // token fetches are retried once, not again for each request attempt
+        let token = server
+            .mock("PUT", "/token")
+            .with_status(500)
+            .expect(4)
+            .create();
+        let key = server.mock("GET", "/key").expect(0).create();
+        let err = client
+            .get(Raw, "http://192.0.2.1/key".to_string())
+            .send::<String>()
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").starts_with("failed to fetch session token"),
+            "{err:#}"
+        );
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::MetadataUnreachable));
+        token.assert();
+        key.assert();
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 3 "), Some(Duration::from_secs(3)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let delay = parse_retry_after(&later).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let mut server = mockito::Server::new();
+        let client = Client::try_new()
+            .unwrap()
+            .max_retries(0)
+            .request_timeout(Duration::from_millis(100))
+            .mock_base_url(server.url());
+
+        server
+            .mock("GET", "/slow")
+            .with_body_from_request(|_| {
+                thread::sleep(Duration::from_millis(500));
+                b"late".to_vec()
+            })
+            .create();
+        let err = client
+            .get(Raw, "http://192.0.2.1/slow".to_string())
+            .send::<String>()
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("request timed out after 0.1s"),
+            "{err:#}"
+        );
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::MetadataUnreachable));
+    }
+
+    #[test]
+    fn test_transport() {
+        #[derive(Debug, Default)]
+        struct Recorder {
+            inner: blocking::Client,
+            requests: Mutex<Vec<String>>,
+        }
+
+        impl Transport for Recorder {
+            fn execute(&self, req: blocking::Request) -> reqwest::Result<blocking::Response> {
+                self.requests
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", req.method(), req.url()));
+                self.inner.execute(req)
+            }
+        }
+
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/key").with_body("value").create();
+        server.mock("POST", "/ready").create();
+        let recorder = Arc::new(Recorder::default());
+        let client = Client::try_new()
+            .unwrap()
+            .max_retries(0)
+            .transport(recorder.clone())
+            .mock_base_url(server.url());
+        let value: Option<String> = client
+            .get(Raw, "http://192.0.2.1/key".to_string())
+            .send()
+            .unwrap();
+        assert_eq!(value.as_deref(), Some("value"));
+        client
+            .post(Raw, "http://192.0.2.1/ready".to_string(), None)
+            .dispatch_post()
+            .unwrap();
+        assert_eq!(
+            *recorder.requests.lock().unwrap(),
+            vec![
+                format!("GET {}/key", server.url()),
+                format!("POST {}/ready", server.url()),
+            ]
+        );
+    }
+}
@@ -14,28 +14,128 @@
 
 //! Drive a functions through a finite number of retries until it succeeds.
 
+use std::sync::{Mutex, OnceLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+
+use crate::errors::ErrorKind;
 
 mod client;
+mod proxy;
 pub mod raw_deserializer;
+mod tls;
+mod token;
+mod transport;
 pub use self::client::*;
+pub use self::proxy::ProxyPolicy;
+pub use self::tls::{set_tls_settings, SpkiPinMismatch, TlsSettings};
+pub use self::token::{Token, TokenSource};
+pub use self::transport::Transport;
+
+/// Process-wide retry policy, overriding built-in defaults.
+static DEFAULT_POLICY: OnceLock<Retry> = OnceLock::new();
+
+/// Set the process-wide default retry policy.
+///
+/// This can only be set once, before any retrying driver is built.
+pub fn set_default_policy(policy: Retry) -> Result<()> {
+    DEFAULT_POLICY
+        .set(policy)
+        .map_err(|_| anyhow!("default retry policy already set"))
+}
+
+/// Process-wide deadline, across all metadata requests and their retries.
+static DEADLINE: Mutex<Option<Deadline>> = Mutex::new(None);
+
+/// Set a process-wide deadline, expiring `timeout` from now.
+///
+/// Once expired, metadata requests give up instead of performing further
+/// attempts. Other retried operations, e.g. mounting a config-drive, are not
+/// bounded by it.
+pub fn set_deadline(timeout: Duration) {
+    *DEADLINE.lock().unwrap() = Some(Deadline::new(timeout));
+}
+
+/// Restart the process-wide deadline from now, if one is set.
+///
+/// This is meant for long-running processes, where each round of fetches
+/// gets its own deadline.
+pub fn rearm_deadline() {
+    if let Some(deadline) = DEADLINE.lock().unwrap().as_mut() {
+        *deadline = Deadline::new(deadline.timeout);
+    }
+}
+
+/// Return the process-wide deadline, if any.
+pub fn deadline() -> Option<Deadline> {
+    *DEADLINE.lock().unwrap()
+}
+
+/// Deadline shared by all retries and requests.
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    timeout: Duration,
+    expires: Instant,
+}
+
+impl Deadline {
+    fn new(timeout: Duration) -> Self {
+        Deadline {
+            timeout,
+            expires: Instant::now() + timeout,
+        }
+    }
+
+    /// Time left before the deadline expires.
+    pub fn remaining(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+
+    /// Describe the deadline, for error messages.
+    pub fn describe(&self) -> String {
+        format!("global deadline of {}s", self.timeout.as_secs_f64())
+    }
+}
+
+/// Upper bound on delays requested by the server for a single retry.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// How a failed attempt should be handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// Retry with the usual backoff.
+    Retry,
+    /// Retry, waiting at least the given delay.
+    RetryAfter(Duration),
+    /// Give up immediately.
+    Fatal,
+}
+
+/// Classifier deciding how a failed attempt should be handled.
+pub type Classifier = fn(&anyhow::Error) -> Verdict;
 
 #[derive(Clone, Debug)]
 pub struct Retry {
     initial_backoff: Duration,
     max_backoff: Duration,
     max_retries: u8,
+    jitter: bool,
+    classifier: Classifier,
 }
 
 impl Default for Retry {
     fn default() -> Self {
+        if let Some(policy) = DEFAULT_POLICY.get() {
+            return policy.clone();
+        }
         Retry {
             initial_backoff: Duration::new(1, 0),
             max_backoff: Duration::new(5, 0),
             max_retries: 10,
+            jitter: true,
+            classifier: |_| Verdict::Retry,
         }
     }
 }
@@ -43,20 +143,19 @@ impl Default for Retry {
 impl Retry {
     /// Build a new retrying driver.
     ///
-    /// This defaults to 10 retries with 5 seconds maximum backoff.
+    /// This defaults to 10 retries with 5 seconds maximum backoff, unless
+    /// a different process-wide policy has been set.
     pub fn new() -> Self {
         Retry::default()
     }
 
     /// Set the initial backoff.
-    #[allow(dead_code)]
     pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
         self.initial_backoff = initial_backoff;
         self
     }
 
     /// Set the maximum backoff.
-    #[allow(dead_code)]
     pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
         self.max_backoff = max_backoff;
         self
@@ -71,14 +170,56 @@ impl Retry {
         self
     }
 
+    /// Randomize backoff delays, between half and all of their nominal value.
+    ///
+    /// This is enabled by default, so that many clients started at the same
+    /// time do not retry in lockstep.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the classifier deciding how failed attempts are handled.
+    ///
+    /// By default, all failures are retried.
+    pub fn classifier(mut self, classifier: Classifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
     /// Retry a function until it either succeeds once or fails all the time.
     pub fn retry<F, R>(self, try_fn: F) -> Result<R>
+    where
+        F: Fn(u8) -> Result<R>,
+    {
+        self.retry_until(None, try_fn)
+    }
+
+    /// Like `retry()`, for metadata requests.
+    ///
+    /// If a process-wide deadline is set, no attempt is started after it
+    /// expires.
+    pub(crate) fn retry_request<F, R>(self, try_fn: F) -> Result<R>
+    where
+        F: Fn(u8) -> Result<R>,
+    {
+        self.retry_until(deadline(), try_fn)
+    }
+
+    fn retry_until<F, R>(self, deadline: Option<Deadline>, try_fn: F) -> Result<R>
     where
         F: Fn(u8) -> Result<R>,
     {
         let mut delay = self.initial_backoff;
         let mut attempts = 0;
 
+        if let Some(deadline) = deadline {
+            if deadline.remaining().is_zero() {
+                return Err(anyhow!("{} exceeded", deadline.describe())
+                    .context(ErrorKind::MetadataUnreachable));
+            }
+        }
+
         loop {
             let res = try_fn(attempts);
 
@@ -88,14 +229,27 @@ impl Retry {
             }
 
             // Otherwise, perform "the retry with backoff" logic.
+            let wait = match res.as_ref().map_err(self.classifier) {
+                Err(Verdict::Fatal) => break res,
+                Err(Verdict::RetryAfter(after)) => after.min(MAX_RETRY_AFTER).max(delay),
+                _ if self.jitter => jitter(delay),
+                _ => delay,
+            };
             if attempts >= self.max_retries {
                 break res.with_context(|| {
                     format!("maximum number of retries ({}) reached", self.max_retries)
                 });
             }
+            if let Some(deadline) = deadline {
+                if deadline.remaining() <= wait {
+                    break res.with_context(|| {
+                        format!("{} reached before next retry", deadline.describe())
+                    });
+                }
+            }
             attempts = attempts.saturating_add(1);
 
-            thread::sleep(delay);
+            thread::sleep(wait);
 
             delay = if self.max_backoff != Duration::new(0, 0) && delay * 2 > self.max_backoff {
                 self.max_backoff
@@ -106,6 +260,17 @@ impl Retry {
     }
 }
 
+/// Randomize a delay, between half and all of its value.
+fn jitter(delay: Duration) -> Duration {
+    let mut buf = [0u8; 4];
+    // Without randomness, fall back to the nominal delay.
+    if openssl::rand::rand_bytes(&mut buf).is_err() {
+        return delay;
+    }
+    let ratio = f64::from(u32::from_ne_bytes(buf)) / f64::from(u32::MAX);
+    delay.mul_f64(0.5 + ratio / 2.0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,4 +319,79 @@ mod tests {
         let total = final_res.unwrap();
         assert_eq!(total, retries);
     }
+
+    #[test]
+    fn test_classifier() {
+        let driver = Retry::new()
+            .initial_backoff(Duration::from_millis(10))
+            .max_retries(5);
+
+        // fatal errors are not retried
+        let res: AttemptResult = driver
+            .clone()
+            .classifier(|_| Verdict::Fatal)
+            .retry(|attempt| {
+                if attempt != 0 {
+                    panic!("unreachable attempt {attempt}");
+                }
+                bail!("expected error")
+            });
+        assert_eq!(format!("{:#}", res.unwrap_err()), "expected error");
+
+        // server-requested delays are honoured
+        let start = Instant::now();
+        let res = driver
+            .classifier(|_| Verdict::RetryAfter(Duration::from_millis(300)))
+            .retry(|attempt| {
+                if attempt == 0 {
+                    bail!("expected error")
+                }
+                AttemptResult::Ok(attempt)
+            });
+        assert_eq!(res.unwrap(), 1);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let delay = Duration::from_secs(4);
+        for _ in 0..100 {
+            let d = jitter(delay);
+            assert!(d >= delay / 2 && d <= delay, "{d:?}");
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let deadline = Deadline::new(Duration::from_millis(200));
+        assert!(deadline.remaining() > Duration::ZERO);
+        assert_eq!(deadline.describe(), "global deadline of 0.2s");
+        let driver = Retry::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(100))
+            .max_retries(100);
+        let err = driver
+            .clone()
+            .retry_until(Some(deadline), |_| -> AttemptResult {
+                bail!("expected error")
+            })
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "global deadline of 0.2s reached before next retry: expected error"
+        );
+
+        thread::sleep(deadline.remaining());
+        assert_eq!(deadline.remaining(), Duration::ZERO);
+        let err = driver
+            .retry_until(Some(deadline), |attempt| -> AttemptResult {
+                panic!("unreachable attempt {attempt}")
+            })
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "metadata service unreachable: global deadline of 0.2s exceeded"
+        );
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::MetadataUnreachable));
+    }
 }
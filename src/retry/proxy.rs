@@ -0,0 +1,170 @@
+//! HTTP proxy policy.
+//!
+//! Metadata services are reached over link-local or otherwise
+//! instance-private addresses, which an HTTP proxy cannot (or must not)
+//! reach: going through a proxy would at best fail, and at worst leak
+//! instance credentials. Clients therefore ignore proxies configured in the
+//! environment, unless their provider talks to public endpoints and
+//! explicitly opts in.
+
+use std::env;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use reqwest::{blocking, Url};
+use slog_scope::debug;
+
+use crate::redact;
+
+/// Hostnames of metadata services, which never go through a proxy.
+const METADATA_HOSTNAMES: &[&str] = &["metadata.google.internal"];
+
+/// Non-link-local addresses of metadata services.
+const METADATA_ADDRS: &[IpAddr] = &[
+    // Aliyun
+    IpAddr::V4(Ipv4Addr::new(100, 100, 100, 200)),
+    // AWS, over IPv6
+    IpAddr::V6(Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254)),
+];
+
+/// Whether requests may go through an HTTP proxy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProxyPolicy {
+    /// Never use a proxy.
+    #[default]
+    Never,
+    /// Use the proxy configured in the environment (`http_proxy`,
+    /// `https_proxy`, `all_proxy` and `no_proxy`, in lower or upper case),
+    /// except for link-local and metadata hosts.
+    Environment,
+}
+
+impl ProxyPolicy {
+    /// Configure a client builder according to this policy.
+    pub(crate) fn apply(self, builder: blocking::ClientBuilder) -> blocking::ClientBuilder {
+        debug!("HTTP proxy policy: {:?}", self);
+        match self {
+            ProxyPolicy::Never => builder.no_proxy(),
+            ProxyPolicy::Environment => {
+                let proxies = EnvProxies::from_env(|name| env::var(name).ok());
+                let proxy = reqwest::Proxy::custom(move |url| proxies.for_url(url))
+                    .no_proxy(reqwest::NoProxy::from_env());
+                builder.no_proxy().proxy(proxy)
+            }
+        }
+    }
+}
+
+/// Proxies configured in the environment.
+#[derive(Clone, Debug, Default)]
+struct EnvProxies {
+    http: Option<String>,
+    https: Option<String>,
+    all: Option<String>,
+}
+
+impl EnvProxies {
+    /// Read proxies from environment variables, lowercase ones first.
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let lookup = |name: &str| {
+            var(name)
+                .or_else(|| var(&name.to_uppercase()))
+                .filter(|v| !v.is_empty())
+        };
+        EnvProxies {
+            http: lookup("http_proxy"),
+            https: lookup("https_proxy"),
+            all: lookup("all_proxy"),
+        }
+    }
+
+    /// Select the proxy for `url`, if any.
+    fn for_url(&self, url: &Url) -> Option<String> {
+        if is_metadata_host(url) {
+            debug!("not using a proxy for metadata host"; "url" => redact::url(url).as_str());
+            return None;
+        }
+        let proxy = match url.scheme() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        }
+        .or(self.all.as_ref())
+        .cloned();
+        if let Some(proxy) = &proxy {
+            debug!("using proxy {}", redact::url_str(proxy); "url" => redact::url(url).as_str());
+        }
+        proxy
+    }
+}
+
+/// Whether `url` points to a link-local or metadata host.
+fn is_metadata_host(url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let Ok(addr) = host.parse::<IpAddr>() else {
+        let name = host.trim_end_matches('.').to_ascii_lowercase();
+        return METADATA_HOSTNAMES.contains(&name.as_str());
+    };
+    let link_local = match addr {
+        IpAddr::V4(addr) => addr.is_link_local(),
+        IpAddr::V6(addr) => (addr.segments()[0] & 0xffc0) == 0xfe80,
+    };
+    link_local || METADATA_ADDRS.contains(&addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_metadata_host() {
+        let cases = [
+            ("http://169.254.169.254/latest", true),
+            ("http://[fe80::1]/", true),
+            ("http://[fd00:ec2::254]/latest", true),
+            ("http://100.100.100.200/latest", true),
+            ("http://metadata.google.internal/computeMetadata/v1", true),
+            ("http://Metadata.Google.Internal./", true),
+            ("https://metadata.packet.net/metadata", false),
+            ("http://10.0.0.1/", false),
+        ];
+        for (url, expected) in cases {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(is_metadata_host(&url), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_env_proxies() {
+        let vars = maplit::hashmap! {
+            "http_proxy" => "http://lower:3128",
+            "HTTP_PROXY" => "http://upper:3128",
+            "HTTPS_PROXY" => "http://secure:3128",
+            "all_proxy" => "",
+        };
+        let proxies = EnvProxies::from_env(|name| vars.get(name).map(ToString::to_string));
+        let for_url = |url: &str| proxies.for_url(&Url::parse(url).unwrap());
+
+        assert_eq!(
+            for_url("http://example.com/").as_deref(),
+            Some("http://lower:3128")
+        );
+        assert_eq!(
+            for_url("https://metadata.packet.net/").as_deref(),
+            Some("http://secure:3128")
+        );
+        assert_eq!(for_url("http://169.254.169.254/"), None);
+
+        let proxies = EnvProxies::from_env(|name| {
+            (name == "ALL_PROXY").then(|| "socks5://fallback:1080".to_string())
+        });
+        assert_eq!(
+            proxies
+                .for_url(&Url::parse("https://example.com/").unwrap())
+                .as_deref(),
+            Some("socks5://fallback:1080")
+        );
+    }
+}
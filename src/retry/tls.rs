@@ -0,0 +1,484 @@
+//! TLS settings for HTTPS metadata endpoints.
+//!
+//! Metadata may be served over HTTPS by endpoints whose certificate is
+//! issued by a private CA, which can be trusted through an extra CA bundle.
+//! Additionally, the public key of the server certificate can be pinned.
+//!
+//! Pins are checked during the TLS handshake, before any request is sent,
+//! by a rustls certificate verifier. Pinned clients only connect over HTTPS
+//! and don't follow redirects.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{anyhow, bail, Context, Result};
+use openssl::base64;
+use openssl::hash::{hash, MessageDigest};
+use openssl::x509::X509;
+use reqwest::{blocking, redirect};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use slog_scope::warn;
+
+use crate::redact;
+
+/// Prefix of SHA-256 SPKI pins, as used by curl's `--pinnedpubkey`.
+const PIN_PREFIX: &str = "sha256//";
+
+/// Process-wide TLS settings.
+static TLS_SETTINGS: OnceLock<TlsSettings> = OnceLock::new();
+
+/// Set the process-wide TLS settings.
+///
+/// This can only be set once, before any client is built.
+pub fn set_tls_settings(settings: TlsSettings) -> Result<()> {
+    TLS_SETTINGS
+        .set(settings)
+        .map_err(|_| anyhow!("TLS settings already set"))
+}
+
+/// Return the process-wide TLS settings, if any.
+pub(crate) fn tls_settings() -> Option<&'static TlsSettings> {
+    TLS_SETTINGS.get()
+}
+
+/// Extra trust anchors and pinned public keys.
+#[derive(Clone, Debug, Default)]
+pub struct TlsSettings {
+    ca_certs: Vec<CertificateDer<'static>>,
+    spki_pins: Vec<String>,
+}
+
+impl TlsSettings {
+    /// Trust the CA certificates in a PEM bundle, in addition to the system
+    /// trust store.
+    pub fn ca_bundle(mut self, path: &Path) -> Result<Self> {
+        let pem = fs::read(path).with_context(|| format!("failed to read CA bundle {path:?}"))?;
+        let certs = X509::stack_from_pem(&pem)
+            .with_context(|| format!("failed to parse CA bundle {path:?}"))?;
+        if certs.is_empty() {
+            bail!("no certificate found in CA bundle {:?}", path);
+        }
+        for cert in certs {
+            let der = cert.to_der().context("failed to encode CA certificate")?;
+            self.ca_certs.push(der.into());
+        }
+        Ok(self)
+    }
+
+    /// Only accept servers whose certificate public key matches one of
+    /// `pins`, in the `sha256//<base64>` format.
+    pub fn spki_pins(mut self, pins: &[String]) -> Result<Self> {
+        for pin in pins {
+            let digest = pin
+                .strip_prefix(PIN_PREFIX)
+                .ok_or_else(|| anyhow!("SPKI pin '{}' doesn't start with '{}'", pin, PIN_PREFIX))?;
+            let decoded = base64::decode_block(digest)
+                .with_context(|| format!("failed to decode SPKI pin '{pin}'"))?;
+            if decoded.len() != 32 {
+                bail!("SPKI pin '{}' is not a SHA-256 digest", pin);
+            }
+            self.spki_pins.push(digest.to_string());
+        }
+        Ok(self)
+    }
+
+    /// Whether server public keys are pinned.
+    pub(crate) fn pinned(&self) -> bool {
+        !self.spki_pins.is_empty()
+    }
+
+    /// Configure a client builder with these settings.
+    pub(crate) fn apply(
+        &self,
+        builder: blocking::ClientBuilder,
+    ) -> Result<blocking::ClientBuilder> {
+        if !self.pinned() {
+            return self.ca_certs.iter().try_fold(builder, |b, der| {
+                let cert =
+                    reqwest::Certificate::from_der(der).context("failed to load CA certificate")?;
+                Ok(b.add_root_certificate(cert))
+            });
+        }
+        let config = self.pinned_config()?;
+        Ok(builder
+            .use_preconfigured_tls(config)
+            .https_only(true)
+            .redirect(redirect::Policy::custom(|attempt| {
+                let url = redact::url(attempt.url()).to_string();
+                attempt.error(RedirectRefused(url))
+            })))
+    }
+
+    /// Build a rustls configuration checking pins during the handshake.
+    ///
+    /// The certificate chain is verified as usual first, against the system
+    /// trust store and the extra CA certificates.
+    fn pinned_config(&self) -> Result<rustls::ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        let native = rustls_native_certs::load_native_certs();
+        for err in native.errors {
+            warn!("failed to load system CA certificates: {}", err);
+        }
+        roots.add_parsable_certificates(native.certs);
+        for der in &self.ca_certs {
+            roots
+                .add(der.clone())
+                .context("failed to load CA certificate")?;
+        }
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let webpki = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .context("failed to build certificate verifier")?;
+        let verifier = PinnedVerifier {
+            webpki,
+            spki_pins: self.spki_pins.clone(),
+        };
+        let config = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .context("failed to configure TLS versions")?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        Ok(config)
+    }
+}
+
+/// Certificate verifier checking pins on top of the usual verification.
+#[derive(Debug)]
+struct PinnedVerifier {
+    webpki: Arc<WebPkiServerVerifier>,
+    spki_pins: Vec<String>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.webpki.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        let pin = spki_pin(end_entity).map_err(|e| rustls::Error::General(format!("{e:#}")))?;
+        if !self.spki_pins.contains(&pin) {
+            let mismatch = SpkiPinMismatch(server_name.to_str().into_owned());
+            return Err(rustls::Error::Other(rustls::OtherError(Arc::new(mismatch))));
+        }
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.webpki.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.webpki.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.webpki.supported_verify_schemes()
+    }
+}
+
+/// Server certificate not matching any pinned public key.
+#[derive(Clone, Debug)]
+pub struct SpkiPinMismatch(String);
+
+impl fmt::Display for SpkiPinMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "server certificate for {} doesn't match any pinned public key",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for SpkiPinMismatch {}
+
+/// Redirect refused because server public keys are pinned.
+#[derive(Clone, Debug)]
+pub struct RedirectRefused(String);
+
+impl fmt::Display for RedirectRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "refusing redirect to {} with pinned public keys", self.0)
+    }
+}
+
+impl std::error::Error for RedirectRefused {}
+
+/// Find the pinning failure which caused a request error, if any.
+///
+/// The failure is buried in I/O and rustls errors, which don't expose it
+/// as their source.
+pub(crate) fn pinning_failure(err: &reqwest::Error) -> Option<anyhow::Error> {
+    let mut next: Option<&(dyn StdError + 'static)> = Some(err);
+    while let Some(e) = next {
+        if let Some(mismatch) = e.downcast_ref::<SpkiPinMismatch>() {
+            return Some(mismatch.clone().into());
+        }
+        if let Some(refused) = e.downcast_ref::<RedirectRefused>() {
+            return Some(refused.clone().into());
+        }
+        next = if let Some(e) = e.downcast_ref::<io::Error>() {
+            e.get_ref().map(|e| e as &(dyn StdError + 'static))
+        } else if let Some(rustls::Error::Other(e)) = e.downcast_ref::<rustls::Error>() {
+            Some(e.0.as_ref())
+        } else {
+            e.source()
+        };
+    }
+    None
+}
+
+/// Compute the base64-encoded SHA-256 digest of a certificate public key.
+fn spki_pin(cert_der: &[u8]) -> Result<String> {
+    let cert = X509::from_der(cert_der).context("failed to parse server certificate")?;
+    let spki = cert
+        .public_key()
+        .and_then(|k| k.public_key_to_der())
+        .context("failed to encode server public key")?;
+    let digest = hash(MessageDigest::sha256(), &spki).context("failed to hash public key")?;
+    Ok(base64::encode_block(&digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use crate::retry::{Client, Raw};
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use openssl::x509::extension::{
+        BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+    };
+    use openssl::x509::X509NameBuilder;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    /// Build a certificate for `key`, self-signed if `issuer` is unset.
+    ///
+    /// Issued certificates are valid for a server on 127.0.0.1, others are
+    /// CA certificates.
+    fn certificate(key: &PKey<Private>, issuer: Option<(&X509, &PKey<Private>)>) -> X509 {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "metadata").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_pubkey(key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let (issuer_name, signing_key) = match issuer {
+            Some((issuer, issuer_key)) => {
+                let san = SubjectAlternativeName::new()
+                    .ip("127.0.0.1")
+                    .build(&cert.x509v3_context(Some(issuer), None))
+                    .unwrap();
+                cert.append_extension(san).unwrap();
+                cert.append_extension(ExtendedKeyUsage::new().server_auth().build().unwrap())
+                    .unwrap();
+                (issuer.subject_name(), issuer_key)
+            }
+            None => {
+                cert.append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                    .unwrap();
+                cert.append_extension(KeyUsage::new().critical().key_cert_sign().build().unwrap())
+                    .unwrap();
+                (name.as_ref(), key)
+            }
+        };
+        cert.set_issuer_name(issuer_name).unwrap();
+        cert.sign(signing_key, MessageDigest::sha256()).unwrap();
+        cert.build()
+    }
+
+    fn self_signed() -> X509 {
+        certificate(&key(), None)
+    }
+
+    /// Serve `response` over HTTPS with a certificate issued by `ca`.
+    ///
+    /// Returns the server URL, and counters of connections and requests.
+    fn serve(
+        ca: (&X509, &PKey<Private>),
+        key: &PKey<Private>,
+        response: &'static str,
+    ) -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let cert = certificate(key, Some(ca));
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(key).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "https://127.0.0.1:{}",
+            listener.local_addr().unwrap().port()
+        );
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (c, r) = (connections.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                c.fetch_add(1, Ordering::SeqCst);
+                let Ok(mut stream) = acceptor.accept(stream.unwrap()) else {
+                    continue;
+                };
+                let mut buf = [0; 4096];
+                if stream.read(&mut buf).unwrap_or(0) > 0 {
+                    r.fetch_add(1, Ordering::SeqCst);
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            }
+        });
+        (url, connections, requests)
+    }
+
+    #[test]
+    fn test_ca_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ca.pem");
+        let mut pem = self_signed().to_pem().unwrap();
+        pem.extend(self_signed().to_pem().unwrap());
+        fs::write(&path, pem).unwrap();
+        let settings = TlsSettings::default().ca_bundle(&path).unwrap();
+        assert_eq!(settings.ca_certs.len(), 2);
+
+        fs::write(&path, "not a certificate").unwrap();
+        TlsSettings::default().ca_bundle(&path).unwrap_err();
+        TlsSettings::default()
+            .ca_bundle(&dir.path().join("missing.pem"))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_spki_pins() {
+        let cert = self_signed();
+        let pin = spki_pin(&cert.to_der().unwrap()).unwrap();
+        let expected = hash(
+            MessageDigest::sha256(),
+            &cert.public_key().unwrap().public_key_to_der().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(base64::decode_block(&pin).unwrap(), expected.to_vec());
+
+        let settings = TlsSettings::default()
+            .spki_pins(&[format!("{PIN_PREFIX}{pin}")])
+            .unwrap();
+        assert_eq!(settings.spki_pins, vec![pin.clone()]);
+
+        for invalid in [
+            pin.clone(),
+            format!("{PIN_PREFIX}!!"),
+            "sha256//AAAA".into(),
+        ] {
+            TlsSettings::default().spki_pins(&[invalid]).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_pinned_handshake() {
+        let ca_key = key();
+        let ca = certificate(&ca_key, None);
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("ca.pem");
+        fs::write(&bundle, ca.to_pem().unwrap()).unwrap();
+        let server_key = key();
+        // certificates for the same key have the same pin
+        let server_cert = certificate(&server_key, Some((&ca, &ca_key)));
+        let pin = format!(
+            "{PIN_PREFIX}{}",
+            spki_pin(&server_cert.to_der().unwrap()).unwrap()
+        );
+        let client = |pin: &str| {
+            let settings = TlsSettings::default()
+                .ca_bundle(&bundle)
+                .unwrap()
+                .spki_pins(&[pin.to_string()])
+                .unwrap();
+            Client::try_new_with_tls_settings(Box::leak(Box::new(settings)))
+                .unwrap()
+                .initial_backoff(Duration::from_millis(10))
+                .max_retries(3)
+        };
+        let ok = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nvalue";
+
+        // matching pin
+        let (url, _, requests) = serve((&ca, &ca_key), &server_key, ok);
+        let value: Option<String> = client(&pin).get(Raw, format!("{url}/key")).send().unwrap();
+        assert_eq!(value.as_deref(), Some("value"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // mismatching pin: the handshake fails before any request is sent,
+        // and is not retried
+        let other = format!(
+            "{PIN_PREFIX}{}",
+            spki_pin(&self_signed().to_der().unwrap()).unwrap()
+        );
+        let (url, connections, requests) = serve((&ca, &ca_key), &server_key, ok);
+        let err = client(&other)
+            .get(Raw, format!("{url}/key"))
+            .send::<String>()
+            .unwrap_err();
+        assert!(err.downcast_ref::<SpkiPinMismatch>().is_some(), "{err:#}");
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        // redirects are refused
+        let redirect = "HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:1/key\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (url, _, requests) = serve((&ca, &ca_key), &server_key, redirect);
+        let err = client(&pin)
+            .get(Raw, format!("{url}/key"))
+            .send::<String>()
+            .unwrap_err();
+        assert!(err.downcast_ref::<RedirectRefused>().is_some(), "{err:#}");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // and so are plain HTTP requests
+        let err = client(&pin)
+            .get(Raw, "http://127.0.0.1:1/key".into())
+            .send::<String>()
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::InvalidConfig));
+    }
+}
@@ -0,0 +1,133 @@
+//! Session tokens for metadata services.
+//!
+//! Some metadata services (e.g. AWS IMDSv2) require a short-lived session
+//! token on each request. A `TokenSource` knows how to fetch such a token;
+//! the client caches it, fetching it lazily on first use and again when it
+//! expires or is rejected by the server.
+
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use reqwest::header::{HeaderName, HeaderValue};
+use slog_scope::debug;
+
+use crate::retry::Client;
+
+/// Source of session tokens, sent to the metadata service in a header.
+pub trait TokenSource: Debug + Send + Sync {
+    /// Name of the header carrying the token.
+    fn header_name(&self) -> HeaderName;
+
+    /// Fetch a new token, using a client without session token.
+    fn fetch(&self, client: &Client) -> Result<Token>;
+}
+
+/// Session token, with its lifetime.
+#[derive(Clone, Debug)]
+pub struct Token {
+    /// Header value, or `None` to send requests without token.
+    value: Option<HeaderValue>,
+    ttl: Duration,
+}
+
+impl Token {
+    /// Build a token valid for `ttl`.
+    ///
+    /// The value is marked as sensitive, so that it doesn't show up in logs.
+    pub fn new(mut value: HeaderValue, ttl: Duration) -> Self {
+        value.set_sensitive(true);
+        Token {
+            value: Some(value),
+            ttl,
+        }
+    }
+
+    /// Build a placeholder for a token which is not available, so that
+    /// requests are sent without it for `ttl`.
+    pub fn absent(ttl: Duration) -> Self {
+        Token { value: None, ttl }
+    }
+}
+
+/// Token cached by a client, shared among its clones.
+#[derive(Debug)]
+pub(crate) struct TokenCache {
+    source: Box<dyn TokenSource>,
+    client: Client,
+    current: Mutex<Option<(Option<HeaderValue>, Instant)>>,
+}
+
+impl TokenCache {
+    /// Build a cache for tokens from `source`, fetched with `client`.
+    pub(crate) fn new(source: Box<dyn TokenSource>, client: Client) -> Self {
+        TokenCache {
+            source,
+            client,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Return the token header, fetching a new token if needed.
+    pub(crate) fn get(&self) -> Result<Option<(HeaderName, HeaderValue)>> {
+        let mut current = self.current.lock().unwrap();
+        let value = match &*current {
+            Some((value, refresh_at)) if Instant::now() < *refresh_at => value.clone(),
+            _ => {
+                let token = self.source.fetch(&self.client)?;
+                debug!("fetched session token, valid for {}s", token.ttl.as_secs());
+                // Refresh a bit early, so that the token doesn't expire
+                // while requests are in flight.
+                let refresh_at = Instant::now() + token.ttl.mul_f64(0.9);
+                *current = Some((token.value.clone(), refresh_at));
+                token.value
+            }
+        };
+        Ok(value.map(|v| (self.source.header_name(), v)))
+    }
+
+    /// Drop the current token, so that the next request fetches a new one.
+    pub(crate) fn invalidate(&self) {
+        *self.current.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Counter(AtomicUsize);
+
+    impl TokenSource for Counter {
+        fn header_name(&self) -> HeaderName {
+            HeaderName::from_static("x-token")
+        }
+
+        fn fetch(&self, _client: &Client) -> Result<Token> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Token::new(
+                HeaderValue::from_str(&format!("token-{n}"))?,
+                Duration::from_millis(200),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_token_cache() {
+        let cache = TokenCache::new(Box::<Counter>::default(), Client::try_new().unwrap());
+        let value = |cache: &TokenCache| cache.get().unwrap().unwrap().1;
+
+        assert_eq!(value(&cache), "token-0");
+        assert_eq!(value(&cache), "token-0");
+
+        cache.invalidate();
+        assert_eq!(value(&cache), "token-1");
+
+        // refreshed on expiry
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(value(&cache), "token-2");
+    }
+}
@@ -0,0 +1,22 @@
+//! HTTP transport.
+//!
+//! `Client` sends requests through a `Transport`, which by default is a
+//! `reqwest` blocking client configured with the proxy policy and TLS
+//! settings. Other transports can be plugged in, e.g. to serve canned
+//! responses in tests or to observe traffic.
+
+use std::fmt::Debug;
+
+use reqwest::blocking;
+
+/// Sender of HTTP requests.
+pub trait Transport: Debug + Send + Sync {
+    /// Send a request and return its response.
+    fn execute(&self, req: blocking::Request) -> reqwest::Result<blocking::Response>;
+}
+
+impl Transport for blocking::Client {
+    fn execute(&self, req: blocking::Request) -> reqwest::Result<blocking::Response> {
+        blocking::Client::execute(self, req)
+    }
+}
@@ -0,0 +1,743 @@
+//! Metadata snapshots.
+//!
+//! A snapshot holds the normalized output of a metadata provider, so that
+//! it can be stored and served again later without querying the provider.
+//! Snapshots can be cached under `/run/afterburn/`, and shared across
+//! multiple afterburn invocations on the same boot.
+
+use std::cell::{RefCell, RefMut};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::BufReader;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use nix::fcntl::{Flock, FlockArg};
+use openssh_keys::PublicKey;
+use serde::{Deserialize, Serialize};
+use slog_scope::{debug, info, warn};
+
+use crate::config::AttributesConfig;
+use crate::metadata;
+use crate::network;
+use crate::providers::MetadataProvider;
+use crate::redact;
+use crate::util::AtomicWrite;
+
+/// Default directory for the snapshot cache.
+pub(crate) const CACHE_DIR: &str = "/run/afterburn";
+
+/// File name of a snapshot, within its directory.
+const SNAPSHOT_FILE: &str = "metadata.json";
+
+/// File name of the lock serializing cache updates, within the cache directory.
+const LOCK_FILE: &str = "metadata.lock";
+
+/// Normalized output of a metadata provider.
+///
+/// Facets are unset until fetched from the provider, so that the cache can
+/// hold partial snapshots.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    /// Provider which produced this snapshot.
+    pub provider: String,
+    /// Creation time, in seconds since UNIX epoch.
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "fetched")]
+    pub hostname: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_keys: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub networks: Option<Vec<network::Interface>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtual_network_devices: Option<Vec<network::VirtualNetDev>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "fetched")]
+    pub netplan_config: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "fetched")]
+    pub rd_network_kargs: Option<Option<String>>,
+}
+
+/// Serialization of fetched facets whose value is optional, keeping `null`
+/// (fetched, without value) apart from a missing field (not fetched).
+mod fetched {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S, T>(
+        value: &Option<Option<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        value
+            .as_ref()
+            .and_then(Option::as_ref)
+            .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+}
+
+impl Snapshot {
+    /// Build a snapshot for the given provider, without any facet.
+    pub(crate) fn new(provider_name: &str) -> Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .context("system clock is before UNIX epoch")?
+            .as_secs();
+        Ok(Self {
+            provider: provider_name.to_string(),
+            timestamp,
+            attributes: None,
+            hostname: None,
+            ssh_keys: None,
+            networks: None,
+            virtual_network_devices: None,
+            netplan_config: None,
+            rd_network_kargs: None,
+        })
+    }
+
+    /// Query all metadata from the given provider.
+    pub(crate) fn capture(provider_name: &str, provider: &dyn MetadataProvider) -> Result<Self> {
+        let mut snapshot = Self::new(provider_name)?;
+        for facet in Facet::ALL {
+            snapshot.fill(facet, provider)?;
+        }
+        Ok(snapshot)
+    }
+
+    /// Whether a facet was fetched.
+    fn has(&self, facet: Facet) -> bool {
+        match facet {
+            Facet::Attributes => self.attributes.is_some(),
+            Facet::Hostname => self.hostname.is_some(),
+            Facet::SshKeys => self.ssh_keys.is_some(),
+            Facet::Networks => self.networks.is_some(),
+            Facet::VirtualNetworkDevices => self.virtual_network_devices.is_some(),
+            Facet::NetplanConfig => self.netplan_config.is_some(),
+            Facet::RdNetworkKargs => self.rd_network_kargs.is_some(),
+        }
+    }
+
+    /// Fetch a facet from the given provider.
+    fn fill(&mut self, facet: Facet, provider: &dyn MetadataProvider) -> Result<()> {
+        fn fill<T>(slot: &mut Option<T>, fetch: impl FnOnce() -> Result<T>) -> Result<()> {
+            *slot = Some(fetch()?);
+            Ok(())
+        }
+        match facet {
+            Facet::Attributes => fill(&mut self.attributes, || {
+                Ok(provider.attributes()?.into_iter().collect())
+            }),
+            Facet::Hostname => fill(&mut self.hostname, || provider.hostname()),
+            Facet::SshKeys => fill(&mut self.ssh_keys, || {
+                Ok(provider
+                    .ssh_keys()?
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect())
+            }),
+            Facet::Networks => fill(&mut self.networks, || provider.networks()),
+            Facet::VirtualNetworkDevices => fill(&mut self.virtual_network_devices, || {
+                provider.virtual_network_devices()
+            }),
+            Facet::NetplanConfig => fill(&mut self.netplan_config, || provider.netplan_config()),
+            Facet::RdNetworkKargs => {
+                fill(&mut self.rd_network_kargs, || provider.rd_network_kargs())
+            }
+        }
+    }
+
+    /// Drop attributes not kept by `policy`.
+    pub(crate) fn filter_attributes(&mut self, policy: &AttributesConfig) {
+        if let Some(attributes) = &mut self.attributes {
+            attributes.retain(|key, _| policy.keeps(key));
+        }
+    }
+
+    /// Fill facets missing from this snapshot with those of `other`.
+    ///
+    /// The result is as old as the oldest of both snapshots.
+    fn merge(&mut self, other: Snapshot) {
+        fn merge<T>(slot: &mut Option<T>, other: Option<T>) {
+            if slot.is_none() {
+                *slot = other;
+            }
+        }
+        merge(&mut self.attributes, other.attributes);
+        merge(&mut self.hostname, other.hostname);
+        merge(&mut self.ssh_keys, other.ssh_keys);
+        merge(&mut self.networks, other.networks);
+        merge(
+            &mut self.virtual_network_devices,
+            other.virtual_network_devices,
+        );
+        merge(&mut self.netplan_config, other.netplan_config);
+        merge(&mut self.rd_network_kargs, other.rd_network_kargs);
+        self.timestamp = self.timestamp.min(other.timestamp);
+    }
+
+    /// Mask the values of sensitive attributes, before sharing this
+    /// snapshot outside of the instance.
+    pub(crate) fn redact(&mut self) {
+        if let Some(attributes) = &mut self.attributes {
+            redact::attributes(attributes);
+        }
+    }
+
+    /// Read a snapshot from the given directory.
+    pub(crate) fn read_from(dir: &Path) -> Result<Self> {
+        let file_path = dir.join(SNAPSHOT_FILE);
+        let file =
+            File::open(&file_path).with_context(|| format!("failed to open file {file_path:?}"))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse metadata snapshot {file_path:?}"))
+    }
+
+    /// Atomically write this snapshot into the given directory.
+    pub(crate) fn write_to(&self, dir: &Path) -> Result<()> {
+        let content =
+            serde_json::to_vec_pretty(self).context("failed to serialize metadata snapshot")?;
+        // only readable by the owner, as it may hold credentials
+        AtomicWrite::default()
+            .mode(0o600)
+            .write(&dir.join(SNAPSHOT_FILE), &content)?;
+        Ok(())
+    }
+
+    /// Return the age of this snapshot.
+    ///
+    /// Snapshots from the future have no meaningful age, and yield `None`.
+    fn age(&self) -> Option<Duration> {
+        let created = SystemTime::UNIX_EPOCH + Duration::from_secs(self.timestamp);
+        SystemTime::now().duration_since(created).ok()
+    }
+}
+
+/// Return a facet of a snapshot, failing if it was not fetched.
+fn recorded<T: Clone>(facet: &Option<T>, name: &str) -> Result<T> {
+    facet
+        .clone()
+        .with_context(|| format!("{name} not present in metadata snapshot"))
+}
+
+impl MetadataProvider for Snapshot {
+    fn recorded_provider(&self) -> Option<&str> {
+        Some(&self.provider)
+    }
+
+    fn attributes(&self) -> Result<HashMap<String, String>> {
+        Ok(recorded(&self.attributes, "attributes")?
+            .into_iter()
+            .collect())
+    }
+
+    fn hostname(&self) -> Result<Option<String>> {
+        recorded(&self.hostname, "hostname")
+    }
+
+    fn ssh_keys(&self) -> Result<Vec<PublicKey>> {
+        recorded(&self.ssh_keys, "SSH keys")?
+            .iter()
+            .map(|k| PublicKey::parse(k).context("failed to parse cached ssh key"))
+            .collect()
+    }
+
+    fn networks(&self) -> Result<Vec<network::Interface>> {
+        recorded(&self.networks, "networks")
+    }
+
+    fn virtual_network_devices(&self) -> Result<Vec<network::VirtualNetDev>> {
+        recorded(&self.virtual_network_devices, "virtual network devices")
+    }
+
+    fn netplan_config(&self) -> Result<Option<String>> {
+        recorded(&self.netplan_config, "netplan configuration")
+    }
+
+    fn rd_network_kargs(&self) -> Result<Option<String>> {
+        recorded(&self.rd_network_kargs, "network kernel arguments")
+    }
+}
+
+/// Cacheable part of provider metadata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Facet {
+    Attributes,
+    Hostname,
+    SshKeys,
+    Networks,
+    VirtualNetworkDevices,
+    NetplanConfig,
+    RdNetworkKargs,
+}
+
+impl Facet {
+    const ALL: [Facet; 7] = [
+        Facet::Attributes,
+        Facet::Hostname,
+        Facet::SshKeys,
+        Facet::Networks,
+        Facet::VirtualNetworkDevices,
+        Facet::NetplanConfig,
+        Facet::RdNetworkKargs,
+    ];
+}
+
+/// Cached provider metadata.
+///
+/// Facets missing from the cached snapshot are fetched from the live
+/// provider on first access, and stored back into the cache; failing to
+/// fetch one leaves the others cached. Boot check-in is not cacheable, thus
+/// it is always forwarded to the live provider.
+struct CachedMetadata {
+    provider: String,
+    cache: SnapshotCache,
+    snapshot: RefCell<Snapshot>,
+    /// Live provider, fetched on demand on cache hits.
+    live: RefCell<Option<Box<dyn MetadataProvider>>>,
+}
+
+impl CachedMetadata {
+    /// Run `f` with the live provider, fetching it on first use.
+    fn with_live<R>(&self, f: impl FnOnce(&dyn MetadataProvider) -> Result<R>) -> Result<R> {
+        let mut live = self.live.borrow_mut();
+        let provider = match live.take() {
+            Some(provider) => provider,
+            None => metadata::fetch_metadata(&self.provider)
+                .context("fetching metadata from provider")?,
+        };
+        f(live.insert(provider).as_ref())
+    }
+
+    /// Return the snapshot, after fetching `facet` if missing.
+    fn get(&self, facet: Facet) -> Result<RefMut<'_, Snapshot>> {
+        let mut snapshot = self.snapshot.borrow_mut();
+        if !snapshot.has(facet) {
+            self.with_live(|provider| snapshot.fill(facet, provider))?;
+            if let Err(e) = self.cache.store(&mut snapshot) {
+                warn!("failed to cache metadata snapshot: {:#}", e);
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+impl MetadataProvider for CachedMetadata {
+    fn attributes(&self) -> Result<HashMap<String, String>> {
+        self.get(Facet::Attributes)?.attributes()
+    }
+
+    fn hostname(&self) -> Result<Option<String>> {
+        self.get(Facet::Hostname)?.hostname()
+    }
+
+    fn ssh_keys(&self) -> Result<Vec<PublicKey>> {
+        self.get(Facet::SshKeys)?.ssh_keys()
+    }
+
+    fn networks(&self) -> Result<Vec<network::Interface>> {
+        self.get(Facet::Networks)?.networks()
+    }
+
+    fn virtual_network_devices(&self) -> Result<Vec<network::VirtualNetDev>> {
+        self.get(Facet::VirtualNetworkDevices)?
+            .virtual_network_devices()
+    }
+
+    fn netplan_config(&self) -> Result<Option<String>> {
+        self.get(Facet::NetplanConfig)?.netplan_config()
+    }
+
+    fn rd_network_kargs(&self) -> Result<Option<String>> {
+        self.get(Facet::RdNetworkKargs)?.rd_network_kargs()
+    }
+
+    fn boot_checkin(&self) -> Result<()> {
+        self.with_live(|live| live.boot_checkin())
+    }
+}
+
+/// On-disk cache for metadata snapshots.
+#[derive(Clone, Debug)]
+pub(crate) struct SnapshotCache {
+    /// Directory holding the cached snapshot.
+    dir: PathBuf,
+    /// Maximum age for a cached snapshot to be considered valid.
+    max_age: Option<Duration>,
+    /// Whether to ignore any existing cached snapshot.
+    refresh: bool,
+}
+
+impl SnapshotCache {
+    /// Build a cache rooted at the given directory.
+    pub(crate) fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_age: None,
+            refresh: false,
+        }
+    }
+
+    /// Set the maximum age for cached snapshots.
+    pub(crate) fn max_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Ignore (and overwrite) any existing cached snapshot.
+    pub(crate) fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Fetch metadata for the given provider, going through the cache.
+    ///
+    /// Each facet of metadata is fetched from the provider and stored on
+    /// first access, unless already cached. Failing to store the snapshot is
+    /// not fatal.
+    pub(crate) fn fetch_metadata(&self, provider: &str) -> Result<Box<dyn MetadataProvider>> {
+        let (snapshot, live) = match self.load(provider) {
+            Some(snapshot) => (snapshot, None),
+            None => (
+                Snapshot::new(provider)?,
+                Some(metadata::fetch_metadata(provider)?),
+            ),
+        };
+        Ok(Box::new(CachedMetadata {
+            provider: provider.to_string(),
+            cache: self.clone(),
+            snapshot: RefCell::new(snapshot),
+            live: RefCell::new(live),
+        }))
+    }
+
+    /// Load a valid cached snapshot for the given provider, if any.
+    fn load(&self, provider: &str) -> Option<Snapshot> {
+        if self.refresh {
+            debug!("snapshot cache: refresh requested, ignoring cached metadata");
+            return None;
+        }
+        let snapshot = self.read(provider)?;
+        info!(
+            "using cached metadata from {:?}",
+            self.dir.join(SNAPSHOT_FILE)
+        );
+        Some(snapshot)
+    }
+
+    /// Read the cached snapshot, if valid for the given provider.
+    fn read(&self, provider: &str) -> Option<Snapshot> {
+        let file_path = self.dir.join(SNAPSHOT_FILE);
+        if !file_path.exists() {
+            debug!("snapshot cache: no cached metadata found");
+            return None;
+        }
+        let snapshot = match Snapshot::read_from(&self.dir) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("ignoring unreadable cached metadata: {:#}", e);
+                return None;
+            }
+        };
+
+        if snapshot.provider != provider {
+            debug!(
+                "snapshot cache: cached metadata is for provider '{}', ignoring",
+                snapshot.provider
+            );
+            return None;
+        }
+        match (snapshot.age(), self.max_age) {
+            (None, _) => {
+                debug!("snapshot cache: cached metadata is from the future, ignoring");
+                return None;
+            }
+            (Some(age), Some(max_age)) if age > max_age => {
+                debug!(
+                    "snapshot cache: cached metadata is {}s old, ignoring",
+                    age.as_secs()
+                );
+                return None;
+            }
+            _ => {}
+        };
+        Some(snapshot)
+    }
+
+    /// Store a snapshot into the cache.
+    ///
+    /// Several invocations may update the cache at the same time, each with
+    /// the facets it needed. Updates are thus serialized with a lock, and
+    /// facets cached meanwhile by others are merged into `snapshot` before
+    /// writing it, unless refreshing.
+    fn store(&self, snapshot: &mut Snapshot) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create directory {:?}", self.dir))?;
+        fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("failed to set permissions on {:?}", self.dir))?;
+
+        let lock_path = self.dir.join(LOCK_FILE);
+        let lock_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&lock_path)
+            .with_context(|| format!("failed to open lock file {lock_path:?}"))?;
+        let _lock = Flock::lock(lock_file, FlockArg::LockExclusive)
+            .map_err(|(_, e)| e)
+            .with_context(|| format!("failed to lock {lock_path:?}"))?;
+
+        if !self.refresh {
+            if let Some(cached) = self.read(&snapshot.provider) {
+                snapshot.merge(cached);
+            }
+        }
+        snapshot.write_to(&self.dir)?;
+        debug!("snapshot cache: stored metadata to {:?}", self.dir);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_snapshot(provider: &str, timestamp: u64) -> Snapshot {
+        Snapshot {
+            provider: provider.to_string(),
+            timestamp,
+            attributes: Some(maplit::btreemap! {
+                "MOCK_INSTANCE_ID".to_string() => "i-1234".to_string(),
+            }),
+            hostname: Some(Some("mock-host".to_string())),
+            ssh_keys: Some(vec![]),
+            networks: Some(vec![network::Interface {
+                name: Some("eth0".to_string()),
+                mac_address: Some(pnet_base::MacAddr(0xf4, 0x00, 0x34, 0x09, 0x73, 0xee)),
+                path: None,
+                priority: 10,
+                nameservers: vec!["192.0.2.53".parse().unwrap()],
+                ip_addresses: vec!["192.0.2.10/24".parse().unwrap()],
+                dhcp: Some(network::DhcpSetting::V6),
+                routes: vec![],
+                bond: None,
+                unmanaged: false,
+                required_for_online: None,
+            }]),
+            virtual_network_devices: Some(vec![]),
+            // fetched, without value
+            netplan_config: Some(None),
+            // not fetched
+            rd_network_kargs: None,
+        }
+    }
+
+    /// Provider counting fetches, whose hostname is unavailable.
+    #[derive(Default)]
+    struct CountingMock(std::rc::Rc<std::cell::Cell<usize>>);
+
+    impl MetadataProvider for CountingMock {
+        fn attributes(&self) -> Result<HashMap<String, String>> {
+            self.0.set(self.0.get() + 1);
+            Ok(maplit::hashmap! {
+                "MOCK_INSTANCE_ID".to_string() => "i-1234".to_string(),
+            })
+        }
+
+        fn hostname(&self) -> Result<Option<String>> {
+            self.0.set(self.0.get() + 1);
+            anyhow::bail!("hostname endpoint unreachable")
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SnapshotCache::new(dir.path());
+        assert_eq!(cache.load("mock"), None);
+
+        let mut snapshot = mock_snapshot("mock", now());
+        cache.store(&mut snapshot).unwrap();
+        assert_eq!(cache.load("mock"), Some(snapshot.clone()));
+
+        // Cached metadata for a different provider is ignored.
+        assert_eq!(cache.load("other"), None);
+
+        // Refreshing ignores cached metadata.
+        let cache = cache.refresh(true);
+        assert_eq!(cache.load("mock"), None);
+    }
+
+    #[test]
+    fn test_cache_max_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SnapshotCache::new(dir.path()).max_age(Some(Duration::from_secs(60)));
+
+        let mut snapshot = mock_snapshot("mock", now() - 30);
+        cache.store(&mut snapshot).unwrap();
+        assert_eq!(cache.load("mock"), Some(snapshot));
+
+        let mut snapshot = mock_snapshot("mock", now() - 120);
+        cache.store(&mut snapshot).unwrap();
+        assert_eq!(cache.load("mock"), None);
+
+        let mut snapshot = mock_snapshot("mock", now() + 3600);
+        cache.store(&mut snapshot).unwrap();
+        assert_eq!(cache.load("mock"), None);
+    }
+
+    #[test]
+    fn test_cache_concurrent_stores() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SnapshotCache::new(dir.path());
+        let timestamp = now();
+
+        // two invocations started from the same (empty) cache, each
+        // fetching a different facet
+        let mut first = Snapshot::new("mock").unwrap();
+        first.timestamp = timestamp;
+        first.attributes = mock_snapshot("mock", timestamp).attributes;
+        let mut second = Snapshot::new("mock").unwrap();
+        second.timestamp = timestamp;
+        second.hostname = Some(Some("mock-host".to_string()));
+
+        cache.store(&mut first).unwrap();
+        cache.store(&mut second).unwrap();
+        let stored = cache.load("mock").unwrap();
+        assert_eq!(stored.attributes, first.attributes);
+        assert_eq!(stored.hostname, Some(Some("mock-host".to_string())));
+        assert_eq!(stored.ssh_keys, None);
+        // the last writer also sees facets cached by others
+        assert_eq!(second, stored);
+
+        // facets cached by another provider are not merged
+        let mut other = Snapshot::new("other").unwrap();
+        other.ssh_keys = Some(vec![]);
+        cache.store(&mut other).unwrap();
+        assert_eq!(cache.load("other").unwrap().attributes, None);
+
+        // refreshing overwrites cached facets
+        let mut refreshed = Snapshot::new("other").unwrap();
+        refreshed.hostname = Some(None);
+        cache.clone().refresh(true).store(&mut refreshed).unwrap();
+        assert_eq!(cache.load("other").unwrap().ssh_keys, None);
+    }
+
+    #[test]
+    fn test_cache_malformed() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(SNAPSHOT_FILE), "not json").unwrap();
+        let cache = SnapshotCache::new(dir.path());
+        assert_eq!(cache.load("mock"), None);
+    }
+
+    #[test]
+    fn test_filter_attributes() {
+        let mut snapshot = mock_snapshot("mock", now());
+        snapshot
+            .attributes
+            .as_mut()
+            .unwrap()
+            .insert("MOCK_IPV4_PUBLIC".into(), "192.0.2.1".into());
+        let policy = AttributesConfig {
+            exclude: vec!["*_PUBLIC".into()],
+            ..Default::default()
+        };
+        snapshot.filter_attributes(&policy);
+        assert_eq!(
+            snapshot.attributes,
+            Some(maplit::btreemap! {
+                "MOCK_INSTANCE_ID".to_string() => "i-1234".to_string(),
+            })
+        );
+
+        // facets which were not fetched are left alone
+        let mut snapshot = Snapshot::new("mock").unwrap();
+        snapshot.filter_attributes(&policy);
+        assert_eq!(snapshot.attributes, None);
+    }
+
+    #[test]
+    fn test_replay_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = mock_snapshot("mock", now());
+        snapshot.write_to(dir.path()).unwrap();
+
+        let provider = format!("{}{}", metadata::REPLAY_PREFIX, dir.path().display());
+        let replay = metadata::fetch_metadata(&provider).unwrap();
+        assert_eq!(
+            replay.attributes().unwrap(),
+            snapshot.attributes.clone().unwrap().into_iter().collect()
+        );
+        assert_eq!(replay.hostname().unwrap(), snapshot.hostname.unwrap());
+        assert_eq!(replay.networks().unwrap(), snapshot.networks.unwrap());
+        assert_eq!(replay.netplan_config().unwrap(), None);
+        // facets which were not recorded are errors
+        replay.rd_network_kargs().unwrap_err();
+
+        let missing = format!("{}{}", metadata::REPLAY_PREFIX, "/nonexistent");
+        assert!(metadata::fetch_metadata(&missing).is_err());
+    }
+
+    #[test]
+    fn test_cache_per_facet() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SnapshotCache::new(dir.path());
+        let cached = |live: CountingMock| CachedMetadata {
+            provider: "mock".to_string(),
+            cache: cache.clone(),
+            snapshot: RefCell::new(
+                cache
+                    .load("mock")
+                    .unwrap_or_else(|| Snapshot::new("mock").unwrap()),
+            ),
+            live: RefCell::new(Some(Box::new(live))),
+        };
+
+        // only accessed facets are fetched, once
+        let live = CountingMock::default();
+        let fetches = live.0.clone();
+        let metadata = cached(live);
+        assert_eq!(metadata.attributes().unwrap().len(), 1);
+        assert_eq!(metadata.attributes().unwrap().len(), 1);
+        assert_eq!(fetches.get(), 1);
+
+        // a failing facet doesn't prevent caching the others
+        metadata.hostname().unwrap_err();
+        assert_eq!(fetches.get(), 2);
+        let stored = cache.load("mock").unwrap();
+        assert!(stored.attributes.is_some());
+        assert_eq!(stored.hostname, None);
+
+        // cached facets are served without querying the provider, missing
+        // ones are fetched
+        let live = CountingMock::default();
+        let fetches = live.0.clone();
+        let metadata = cached(live);
+        assert_eq!(metadata.attributes().unwrap().len(), 1);
+        assert_eq!(fetches.get(), 0);
+        metadata.hostname().unwrap_err();
+        assert_eq!(fetches.get(), 1);
+    }
+}
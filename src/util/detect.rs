@@ -0,0 +1,459 @@
+//! Platform detection from local signals.
+//!
+//! This is used on systems where the platform ID is not provided on the
+//! kernel command-line. It inspects DMI/SMBIOS strings, config-drive
+//! filesystem labels and the CPU hypervisor signature, and returns a list
+//! of candidate platforms ranked by confidence.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use slog_scope::{info, trace};
+
+/// Relative path to DMI identification strings in sysfs.
+const DMI_ID_DIR: &str = "sys/class/dmi/id";
+
+/// Relative path to filesystem labels populated by udev.
+const DISK_BY_LABEL_DIR: &str = "dev/disk/by-label";
+
+/// Maximum confidence for a single guess.
+const MAX_CONFIDENCE: u8 = 100;
+
+/// Minimum confidence for a guess to be picked as the platform.
+///
+/// Filesystem labels alone are shared by several platforms, and are not
+/// enough.
+const MIN_CONFIDENCE: u8 = 50;
+
+/// A DMI string matching rule.
+struct DmiRule {
+    /// DMI field name (file under `/sys/class/dmi/id`).
+    field: &'static str,
+    /// Substring to look for in the DMI field value.
+    needle: &'static str,
+    /// Platform ID hinted by this rule.
+    platform: &'static str,
+    /// Confidence added when this rule matches.
+    confidence: u8,
+}
+
+const DMI_RULES: &[DmiRule] = &[
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Akamai",
+        platform: "akamai",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Linode",
+        platform: "akamai",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Alibaba Cloud",
+        platform: "aliyun",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Amazon EC2",
+        platform: "aws",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "bios_version",
+        needle: "amazon",
+        platform: "aws",
+        confidence: 80,
+    },
+    // Well-known asset tag for Azure (and Azure Stack Hub) instances.
+    DmiRule {
+        field: "chassis_asset_tag",
+        needle: "7783-7084-3265-9085-8269-3286-77",
+        platform: "azure",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "product_name",
+        needle: "CloudStack",
+        platform: "cloudstack-metadata",
+        confidence: 80,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "DigitalOcean",
+        platform: "digitalocean",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Exoscale",
+        platform: "exoscale",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "product_name",
+        needle: "Google Compute Engine",
+        platform: "gcp",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Hetzner",
+        platform: "hetzner",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "chassis_asset_tag",
+        needle: "ibmcloud",
+        platform: "ibmcloud",
+        confidence: 80,
+    },
+    DmiRule {
+        field: "product_name",
+        needle: "KubeVirt",
+        platform: "kubevirt",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "product_name",
+        needle: "OpenStack",
+        platform: "openstack",
+        confidence: 80,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Equinix",
+        platform: "packet",
+        confidence: 80,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Scaleway",
+        platform: "scaleway",
+        confidence: 90,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "VMware",
+        platform: "vmware",
+        confidence: 80,
+    },
+    DmiRule {
+        field: "sys_vendor",
+        needle: "Vultr",
+        platform: "vultr",
+        confidence: 90,
+    },
+];
+
+/// Config-drive filesystem labels, and the platforms using them.
+const LABEL_RULES: &[(&str, &[&str], u8)] = &[
+    (
+        "config-2",
+        &[
+            "openstack",
+            "cloudstack-configdrive",
+            "ibmcloud-classic",
+            "kubevirt",
+            "powervs",
+        ],
+        20,
+    ),
+    ("CONFIG-2", &["cloudstack-configdrive"], 20),
+    ("cidata", &["proxmoxve", "ibmcloud"], 20),
+];
+
+/// Confidence added when the CPU reports a VMware hypervisor.
+const VMWARE_CPU_CONFIDENCE: u8 = 90;
+
+/// A candidate platform, with the evidence supporting it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlatformGuess {
+    /// Platform ID, as used for `ignition.platform.id`.
+    pub platform: String,
+    /// Confidence score, between 0 and 100.
+    pub confidence: u8,
+    /// Human-readable list of local signals supporting this guess.
+    pub evidence: Vec<String>,
+}
+
+/// Local signals source for platform detection.
+#[derive(Clone, Debug)]
+pub struct Detector {
+    /// Root of the filesystem tree to inspect.
+    root: PathBuf,
+    /// Whether to query the CPU for a hypervisor signature.
+    probe_cpu: bool,
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("/"),
+            probe_cpu: true,
+        }
+    }
+}
+
+impl Detector {
+    /// Build a detector inspecting the given filesystem root.
+    ///
+    /// The CPU is not probed, as it cannot be faked.
+    #[cfg(test)]
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            probe_cpu: false,
+        }
+    }
+
+    /// Return all candidate platforms, most likely first.
+    pub fn detect(&self) -> Vec<PlatformGuess> {
+        let mut guesses: BTreeMap<&str, PlatformGuess> = BTreeMap::new();
+        let mut add = |platform: &'static str, confidence: u8, evidence: String| {
+            trace!("platform detection: {} ({})", platform, evidence);
+            let guess = guesses.entry(platform).or_insert_with(|| PlatformGuess {
+                platform: platform.to_string(),
+                confidence: 0,
+                evidence: vec![],
+            });
+            guess.confidence = guess
+                .confidence
+                .saturating_add(confidence)
+                .min(MAX_CONFIDENCE);
+            guess.evidence.push(evidence);
+        };
+
+        let dmi_dir = self.root.join(DMI_ID_DIR);
+        for rule in DMI_RULES {
+            if let Some(value) = read_dmi_field(&dmi_dir, rule.field) {
+                if value.contains(rule.needle) {
+                    let evidence = format!("DMI {} is '{}'", rule.field, value);
+                    add(rule.platform, rule.confidence, evidence);
+                }
+            }
+        }
+
+        let labels_dir = self.root.join(DISK_BY_LABEL_DIR);
+        for (label, platforms, confidence) in LABEL_RULES {
+            if labels_dir.join(label).exists() {
+                for platform in *platforms {
+                    let evidence = format!("filesystem with label '{label}' found");
+                    add(platform, *confidence, evidence);
+                }
+            }
+        }
+
+        if self.probe_cpu && is_vmware_cpu() {
+            add(
+                "vmware",
+                VMWARE_CPU_CONFIDENCE,
+                "CPU reports VMware hypervisor".to_string(),
+            );
+        }
+
+        let mut ranked: Vec<_> = guesses.into_values().collect();
+        ranked.sort_by_key(|g| std::cmp::Reverse(g.confidence));
+        ranked
+    }
+
+    /// Return the most likely platform.
+    ///
+    /// This fails if no candidate reaches the minimum confidence, or if
+    /// several candidates share the best score.
+    pub fn detect_platform(&self) -> Result<String> {
+        let guesses = self.detect();
+        let Some(best) = guesses.first() else {
+            bail!("unable to detect platform from local signals");
+        };
+        let describe = |guesses: &[PlatformGuess]| {
+            guesses
+                .iter()
+                .map(|g| format!("{} ({})", g.platform, g.confidence))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if best.confidence < MIN_CONFIDENCE {
+            bail!(
+                "unable to detect platform from local signals with enough confidence, candidates: {}",
+                describe(&guesses)
+            );
+        }
+        let tied = guesses
+            .iter()
+            .take_while(|g| g.confidence == best.confidence)
+            .count();
+        if tied > 1 {
+            bail!(
+                "unable to detect platform from local signals, candidates are tied: {}",
+                describe(&guesses[..tied])
+            );
+        }
+        info!(
+            "detected platform '{}' (confidence {}): {}",
+            best.platform,
+            best.confidence,
+            best.evidence.join(", ")
+        );
+        Ok(best.platform.clone())
+    }
+}
+
+/// Detect the current platform, returning the most likely candidate.
+pub fn detect_platform() -> Result<String> {
+    Detector::default().detect_platform()
+}
+
+/// Read and trim a DMI field, ignoring missing or unreadable entries.
+fn read_dmi_field(dmi_dir: &Path, field: &str) -> Option<String> {
+    let value = std::fs::read_to_string(dmi_dir.join(field)).ok()?;
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
+        fn is_vmware_cpu() -> bool {
+            vmw_backdoor::is_vmware_cpu()
+        }
+    } else {
+        fn is_vmware_cpu() -> bool {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_file(root: &Path, rel: &str, content: &str) {
+        let path = root.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_rules_platforms_registered() {
+        let platforms = DMI_RULES
+            .iter()
+            .map(|r| r.platform)
+            .chain(LABEL_RULES.iter().flat_map(|(_, p, _)| p.iter().copied()));
+        for platform in platforms {
+            assert!(
+                crate::metadata::lookup_provider(platform).is_some(),
+                "unknown platform '{platform}'"
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_empty_root() {
+        let root = tempfile::tempdir().unwrap();
+        let guesses = Detector::with_root(root.path()).detect();
+        assert_eq!(guesses, vec![]);
+    }
+
+    #[test]
+    fn test_detect_dmi() {
+        let cases = vec![
+            ("sys_vendor", "Amazon EC2\n", "aws"),
+            ("product_name", "Google Compute Engine\n", "gcp"),
+            ("sys_vendor", "Hetzner\n", "hetzner"),
+            (
+                "chassis_asset_tag",
+                "7783-7084-3265-9085-8269-3286-77\n",
+                "azure",
+            ),
+            ("sys_vendor", "VMware, Inc.\n", "vmware"),
+        ];
+        for (field, value, platform) in cases {
+            let root = tempfile::tempdir().unwrap();
+            write_file(root.path(), &format!("{DMI_ID_DIR}/{field}"), value);
+            let guesses = Detector::with_root(root.path()).detect();
+            assert_eq!(guesses.len(), 1, "{guesses:?}");
+            assert_eq!(guesses[0].platform, platform);
+            assert_eq!(
+                guesses[0].evidence,
+                vec![format!("DMI {} is '{}'", field, value.trim())]
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_ranking() {
+        let root = tempfile::tempdir().unwrap();
+        write_file(
+            root.path(),
+            &format!("{DMI_ID_DIR}/product_name"),
+            "OpenStack Compute\n",
+        );
+        write_file(root.path(), &format!("{DISK_BY_LABEL_DIR}/config-2"), "");
+
+        let guesses = Detector::with_root(root.path()).detect();
+        assert_eq!(guesses[0].platform, "openstack");
+        assert_eq!(guesses[0].confidence, 100);
+        assert_eq!(guesses[0].evidence.len(), 2);
+        assert!(guesses[1..].iter().all(|g| g.confidence == 20));
+        assert!(guesses.iter().any(|g| g.platform == "kubevirt"));
+    }
+
+    #[test]
+    fn test_detect_platform() {
+        // shared config-drive label only
+        let root = tempfile::tempdir().unwrap();
+        write_file(root.path(), &format!("{DISK_BY_LABEL_DIR}/config-2"), "");
+        let err = Detector::with_root(root.path())
+            .detect_platform()
+            .unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("enough confidence"), "{msg}");
+        for platform in ["openstack", "cloudstack-configdrive", "kubevirt"] {
+            assert!(msg.contains(platform), "{msg}");
+        }
+
+        // tied candidates
+        write_file(
+            root.path(),
+            &format!("{DMI_ID_DIR}/sys_vendor"),
+            "Amazon EC2\n",
+        );
+        write_file(
+            root.path(),
+            &format!("{DMI_ID_DIR}/product_name"),
+            "Google Compute Engine\n",
+        );
+        let err = Detector::with_root(root.path())
+            .detect_platform()
+            .unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("tied: "), "{msg}");
+        assert!(
+            msg.contains("aws (90)") && msg.contains("gcp (90)"),
+            "{msg}"
+        );
+        assert!(!msg.contains("openstack"), "{msg}");
+
+        // config-drive label confirming DMI strings
+        let root = tempfile::tempdir().unwrap();
+        write_file(
+            root.path(),
+            &format!("{DMI_ID_DIR}/product_name"),
+            "OpenStack Compute\n",
+        );
+        write_file(root.path(), &format!("{DISK_BY_LABEL_DIR}/config-2"), "");
+        assert_eq!(
+            Detector::with_root(root.path()).detect_platform().unwrap(),
+            "openstack"
+        );
+    }
+}
@@ -20,12 +20,18 @@ use std::io::{BufRead, BufReader, Read};
 mod cmdline;
 pub use self::cmdline::{get_platform, has_network_kargs};
 
+mod detect;
+pub use self::detect::{detect_platform, Detector};
+
 mod dhcp;
 pub use self::dhcp::DhcpOption;
 
 mod mount;
 pub(crate) use mount::{mount_ro, unmount};
 
+mod write;
+pub(crate) use self::write::{remove_file, AtomicWrite, FileOwner, WriteOutcome};
+
 fn key_lookup_line(delim: char, key: &str, line: &str) -> Option<String> {
     match line.find(delim) {
         Some(index) => {
@@ -0,0 +1,342 @@
+//! Atomic file writes.
+//!
+//! Output files are written to a temporary file in the same directory,
+//! synced and renamed over the destination, and the directory is synced
+//! too. Readers thus see either the old or the new content, even if
+//! afterburn or the machine crashes midway.
+//!
+//! Symlinks to output files are kept, and their target is written instead.
+//! Existing files keep their permissions, unless a mode is configured.
+
+use std::borrow::Cow;
+use std::fs::{self, File, Metadata, Permissions};
+use std::io::{self, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Default permissions of new files.
+const DEFAULT_MODE: u32 = 0o644;
+
+/// Outcome of a write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum WriteOutcome {
+    /// The file was written.
+    Written,
+    /// The file already had the expected content, mode and owner, and was
+    /// left untouched.
+    Unchanged,
+}
+
+/// Owner of a written file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileOwner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl FileOwner {
+    /// Parse an owner spec, as `user` or `user:group`, with names or
+    /// numeric IDs. Without a group, the user's primary group is used.
+    pub(crate) fn parse(spec: &str) -> Result<Self> {
+        let (user, group) = match spec.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (spec, None),
+        };
+        let (uid, primary_gid) = match user.parse::<u32>() {
+            Ok(uid) => (
+                uid,
+                uzers::get_user_by_uid(uid).map(|u| u.primary_group_id()),
+            ),
+            Err(_) => {
+                let user = uzers::get_user_by_name(user)
+                    .ok_or_else(|| anyhow!("could not find user {:?}", user))?;
+                (user.uid(), Some(user.primary_group_id()))
+            }
+        };
+        let gid = match group {
+            Some(group) => match group.parse::<u32>() {
+                Ok(gid) => gid,
+                Err(_) => uzers::get_group_by_name(group)
+                    .ok_or_else(|| anyhow!("could not find group {:?}", group))?
+                    .gid(),
+            },
+            None => primary_gid
+                .ok_or_else(|| anyhow!("could not find primary group of user {:?}", user))?,
+        };
+        Ok(FileOwner { uid, gid })
+    }
+}
+
+impl std::fmt::Display for FileOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.uid, self.gid)
+    }
+}
+
+/// Atomic writer of files.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AtomicWrite {
+    mode: Option<u32>,
+    owner: Option<FileOwner>,
+}
+
+impl AtomicWrite {
+    /// Set the permissions of written files, instead of keeping those of
+    /// existing files.
+    pub(crate) fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set the owner of written files, instead of the current user.
+    pub(crate) fn owner(mut self, owner: Option<FileOwner>) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    /// Permissions a file is written with, given those of the existing
+    /// file, if any.
+    pub(crate) fn file_mode(&self, current: Option<u32>) -> u32 {
+        self.mode.or(current).unwrap_or(DEFAULT_MODE)
+    }
+
+    /// Owner of written files, if not the current user.
+    pub(crate) fn file_owner(&self) -> Option<FileOwner> {
+        self.owner
+    }
+
+    /// Write `content` to `path`, creating parent directories as needed.
+    ///
+    /// If `path` is a symlink, its target is written. If the file already
+    /// exists with the same content, mode and owner, it is not rewritten.
+    pub(crate) fn write(&self, path: &Path, content: &[u8]) -> Result<WriteOutcome> {
+        let path = &*resolve_symlink(path)?;
+        let dir = match path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => bail!("could not get parent directory of {:?}", path),
+        };
+        fs::create_dir_all(dir).with_context(|| format!("failed to create directory {dir:?}"))?;
+
+        let current = match fs::metadata(path) {
+            Ok(m) => Some(m),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("failed to stat file {path:?}")),
+        };
+        let mode = self.file_mode(current.as_ref().map(|m| m.mode() & 0o7777));
+        if self.is_unchanged(path, current.as_ref(), mode, content)? {
+            return Ok(WriteOutcome::Unchanged);
+        }
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("could not get file name of {:?}", path))?;
+        let mut temp_file = tempfile::Builder::new()
+            .prefix(&format!(".{}-", file_name.to_string_lossy()))
+            .tempfile_in(dir)
+            .context("failed to create temporary file")?;
+        let temp_path = temp_file.path().to_path_buf();
+        temp_file
+            .write_all(content)
+            .with_context(|| format!("failed to write to file {temp_path:?}"))?;
+        let file = temp_file.as_file();
+        if let Some(owner) = self.owner {
+            std::os::unix::fs::fchown(file, Some(owner.uid), Some(owner.gid))
+                .with_context(|| format!("failed to change owner of file {temp_path:?}"))?;
+        }
+        file.set_permissions(Permissions::from_mode(mode))
+            .with_context(|| format!("failed to set permissions of file {temp_path:?}"))?;
+        file.sync_all()
+            .with_context(|| format!("failed to sync file {temp_path:?}"))?;
+
+        // don't leak temporary file on error
+        temp_file
+            .persist(path)
+            .map_err(|e| {
+                e.file.close().ok();
+                e.error
+            })
+            .with_context(|| format!("failed to persist file {path:?}"))?;
+        sync_dir(dir)?;
+        Ok(WriteOutcome::Written)
+    }
+
+    /// Whether `path`, with the given metadata, already has the expected
+    /// content, mode and owner.
+    fn is_unchanged(
+        &self,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        mode: u32,
+        content: &[u8],
+    ) -> Result<bool> {
+        let Some(metadata) = metadata else {
+            return Ok(false);
+        };
+        if !metadata.is_file()
+            || metadata.len() != content.len() as u64
+            || metadata.mode() & 0o7777 != mode
+            || self
+                .owner
+                .is_some_and(|o| (o.uid, o.gid) != (metadata.uid(), metadata.gid()))
+        {
+            return Ok(false);
+        }
+        let existing = fs::read(path).with_context(|| format!("failed to read file {path:?}"))?;
+        Ok(existing == content)
+    }
+}
+
+/// Resolve `path` if it is a symlink, so that the link is kept and its
+/// target written instead. Dangling symlinks are refused.
+fn resolve_symlink(path: &Path) -> Result<Cow<'_, Path>> {
+    match fs::symlink_metadata(path) {
+        Ok(m) if m.file_type().is_symlink() => fs::canonicalize(path)
+            .map(Cow::Owned)
+            .with_context(|| format!("failed to resolve symlink {path:?}")),
+        _ => Ok(Cow::Borrowed(path)),
+    }
+}
+
+/// Remove `path` if it exists, syncing its directory.
+///
+/// Returns whether the file existed.
+pub(crate) fn remove_file(path: &Path) -> Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("failed to remove file {path:?}")),
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        sync_dir(dir)?;
+    }
+    Ok(true)
+}
+
+/// Sync a directory, to persist renames and removals of its entries.
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)
+        .and_then(|d| d.sync_all())
+        .with_context(|| format!("failed to sync directory {dir:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub/dir/file");
+        let mode = |path: &Path| fs::metadata(path).unwrap().mode() & 0o7777;
+
+        let writer = AtomicWrite::default();
+        assert_eq!(
+            writer.write(&path, b"one\n").unwrap(),
+            WriteOutcome::Written
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"one\n");
+        assert_eq!(mode(&path), DEFAULT_MODE);
+
+        // same content is skipped, without touching the file
+        let inode = fs::metadata(&path).unwrap().ino();
+        assert_eq!(
+            writer.write(&path, b"one\n").unwrap(),
+            WriteOutcome::Unchanged
+        );
+        assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+
+        // new content or mode replaces the file
+        assert_eq!(
+            writer.write(&path, b"two\n").unwrap(),
+            WriteOutcome::Written
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"two\n");
+        let writer = writer.mode(0o600);
+        assert_eq!(
+            writer.write(&path, b"two\n").unwrap(),
+            WriteOutcome::Written
+        );
+        assert_eq!(mode(&path), 0o600);
+
+        // without a configured mode, that of the existing file is kept
+        assert_eq!(
+            AtomicWrite::default().write(&path, b"two\n").unwrap(),
+            WriteOutcome::Unchanged
+        );
+        assert_eq!(
+            AtomicWrite::default().write(&path, b"three\n").unwrap(),
+            WriteOutcome::Written
+        );
+        assert_eq!(mode(&path), 0o600);
+
+        // no temporary file is left behind
+        let entries: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        assert!(remove_file(&path).unwrap());
+        assert!(!remove_file(&path).unwrap());
+    }
+
+    #[test]
+    fn test_atomic_write_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        let link = dir.path().join("link");
+        fs::write(&target, "old\n").unwrap();
+        fs::set_permissions(&target, Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // the link is kept, and its target written with its own mode
+        let writer = AtomicWrite::default();
+        assert_eq!(
+            writer.write(&link, b"new\n").unwrap(),
+            WriteOutcome::Written
+        );
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), b"new\n");
+        assert_eq!(fs::metadata(&target).unwrap().mode() & 0o7777, 0o640);
+        assert_eq!(
+            writer.write(&link, b"new\n").unwrap(),
+            WriteOutcome::Unchanged
+        );
+
+        // dangling links are refused
+        let dangling = dir.path().join("dangling");
+        std::os::unix::fs::symlink(dir.path().join("missing"), &dangling).unwrap();
+        writer.write(&dangling, b"new\n").unwrap_err();
+        assert!(!dir.path().join("missing").exists());
+    }
+
+    #[test]
+    fn test_file_owner() {
+        let uid = nix::unistd::getuid().as_raw();
+        let gid = nix::unistd::getgid().as_raw();
+        assert_eq!(
+            FileOwner::parse(&format!("{uid}:{gid}")).unwrap(),
+            FileOwner { uid, gid }
+        );
+        assert_eq!(
+            FileOwner::parse("0:0").unwrap(),
+            FileOwner { uid: 0, gid: 0 }
+        );
+        assert_eq!(
+            FileOwner::parse("root").unwrap(),
+            FileOwner { uid: 0, gid: 0 }
+        );
+        FileOwner::parse("no-such-user-afterburn").unwrap_err();
+        FileOwner::parse("root:no-such-group-afterburn").unwrap_err();
+
+        // writing with the current owner is allowed for any user
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let writer = AtomicWrite::default().owner(Some(FileOwner { uid, gid }));
+        assert_eq!(writer.write(&path, b"x").unwrap(), WriteOutcome::Written);
+        assert_eq!(writer.write(&path, b"x").unwrap(), WriteOutcome::Unchanged);
+    }
+}
@@ -0,0 +1,778 @@
+//! End-to-end tests of the `afterburn` binary.
+//!
+//! Each test runs the real binary against a mock metadata server, selected
+//! with `--metadata-url`, and checks the files it writes. Configuration is
+//! loaded from the test directory, through `AFTERBURN_CONFIG_DIR`, instead of
+//! `/etc/afterburn`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use mockito::{Matcher, Server, ServerGuard};
+
+/// Output files of a `multi` run, and configuration directory.
+struct Outputs {
+    dir: tempfile::TempDir,
+    attributes: PathBuf,
+    hostname: PathBuf,
+}
+
+impl Outputs {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        Outputs {
+            attributes: dir.path().join("attributes"),
+            hostname: dir.path().join("hostname"),
+            dir,
+        }
+    }
+
+    /// Write the main configuration file.
+    fn config(&self, content: &str) {
+        fs::write(self.dir.path().join("afterburn.toml"), content).unwrap();
+    }
+
+    /// Return the attributes file lines, sorted.
+    fn attributes(&self) -> Vec<String> {
+        let mut lines: Vec<_> = read(&self.attributes).lines().map(String::from).collect();
+        lines.sort();
+        lines
+    }
+
+    fn hostname(&self) -> String {
+        read(&self.hostname).trim_end().to_string()
+    }
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| panic!("reading {path:?}: {e}"))
+}
+
+/// Build a mock server answering 404 to unknown paths.
+fn server() -> ServerGuard {
+    let mut server = Server::new();
+    // Mocks created later take precedence.
+    server
+        .mock("GET", Matcher::Any)
+        .with_status(404)
+        .expect_at_least(0)
+        .create();
+    server
+}
+
+/// Serve `body` at `path`.
+fn serve(server: &mut ServerGuard, path: &str, body: &str) {
+    server
+        .mock("GET", path)
+        .with_body(body)
+        .expect_at_least(1)
+        .create();
+}
+
+/// Run `afterburn multi` for `provider`, writing attributes and hostname.
+fn multi(provider: &str, server: &ServerGuard, outputs: &Outputs) -> Output {
+    multi_with(provider, server, outputs, &[])
+}
+
+/// Like `multi()`, with extra arguments.
+fn multi_with(provider: &str, server: &ServerGuard, outputs: &Outputs, args: &[&str]) -> Output {
+    run(afterburn(outputs)
+        .arg("multi")
+        .args(args)
+        .args(["--provider", provider])
+        .args(["--metadata-url", &server.url()])
+        .arg("--attributes")
+        .arg(&outputs.attributes)
+        .arg("--hostname")
+        .arg(&outputs.hostname))
+}
+
+/// Build a command running the binary, isolated from the host environment.
+fn afterburn(outputs: &Outputs) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_afterburn"));
+    command
+        .env("AFTERBURN_CONFIG_DIR", outputs.dir.path())
+        .env_remove("AFTERBURN_METADATA_URL")
+        .env_remove("HTTP_PROXY")
+        .env_remove("HTTPS_PROXY")
+        .env_remove("ALL_PROXY")
+        .env_remove("http_proxy")
+        .env_remove("https_proxy")
+        .env_remove("all_proxy")
+        .env("RUST_BACKTRACE", "0");
+    command
+}
+
+fn run(command: &mut Command) -> Output {
+    let output = command.output().unwrap();
+    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+fn lines(expected: &[&str]) -> Vec<String> {
+    let mut lines: Vec<_> = expected.iter().map(ToString::to_string).collect();
+    lines.sort();
+    lines
+}
+
+#[test]
+fn test_aliyun() {
+    let mut server = server();
+    let base = "/latest/meta-data";
+    for (key, value) in [
+        ("hostname", "aliyun-host"),
+        ("instance-id", "i-123"),
+        ("instance/instance-type", "ecs.g6.large"),
+        ("private-ipv4", "172.16.0.2"),
+        ("region-id", "cn-hangzhou"),
+    ] {
+        serve(&mut server, &format!("{base}/{key}"), value);
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("aliyun", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_ALIYUN_HOSTNAME=aliyun-host",
+            "AFTERBURN_ALIYUN_INSTANCE_ID=i-123",
+            "AFTERBURN_ALIYUN_INSTANCE_TYPE=ecs.g6.large",
+            "AFTERBURN_ALIYUN_IPV4_PRIVATE=172.16.0.2",
+            "AFTERBURN_ALIYUN_REGION_ID=cn-hangzhou",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "aliyun-host");
+}
+
+#[test]
+fn test_akamai() {
+    let mut server = server();
+    server
+        .mock("PUT", "/v1/token")
+        .with_body("akamai-token")
+        .create();
+    for (key, value) in [
+        (
+            "instance",
+            r#"{
+                "id": 123,
+                "host_uuid": "host-1",
+                "label": "linode-1",
+                "region": "us-ord",
+                "type": "g6-nanode-1",
+                "specs": {"vcpus": 1, "memory": 1024, "gpus": 0, "transfer": 1000, "disk": 25600},
+                "backups": {"enabled": false, "status": null},
+                "tags": ["a", "b"]
+            }"#,
+        ),
+        (
+            "network",
+            r#"{
+                "interfaces": [{"id": 1, "purpose": "public", "label": null, "ipam_address": null}],
+                "ipv4": {"public": ["192.0.2.10/32"], "private": [], "shared": []},
+                "ipv6": {"slaac": "2001:db8::10/128", "link_local": "fe80::1/128", "ranges": [], "shared_ranges": []}
+            }"#,
+        ),
+    ] {
+        server
+            .mock("GET", format!("/v1/{key}").as_str())
+            .match_header("metadata-token", "akamai-token")
+            .with_body(value)
+            .expect_at_least(1)
+            .create();
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("akamai", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_AKAMAI_INSTANCE_BACKUPS_ENABLED=false",
+            "AFTERBURN_AKAMAI_INSTANCE_DISK=25600",
+            "AFTERBURN_AKAMAI_INSTANCE_GPUS=0",
+            "AFTERBURN_AKAMAI_INSTANCE_HOST_UUID=host-1",
+            "AFTERBURN_AKAMAI_INSTANCE_ID=123",
+            "AFTERBURN_AKAMAI_INSTANCE_LABEL=linode-1",
+            "AFTERBURN_AKAMAI_INSTANCE_MEMORY=1024",
+            "AFTERBURN_AKAMAI_INSTANCE_REGION=us-ord",
+            "AFTERBURN_AKAMAI_INSTANCE_TAGS=a:b",
+            "AFTERBURN_AKAMAI_INSTANCE_TRANSFER=1000",
+            "AFTERBURN_AKAMAI_INSTANCE_TYPE=g6-nanode-1",
+            "AFTERBURN_AKAMAI_INSTANCE_VCPUS=1",
+            "AFTERBURN_AKAMAI_IPV6_LINK_LOCAL=fe80::1/128",
+            "AFTERBURN_AKAMAI_IPV6_SLAAC=2001:db8::10/128",
+            "AFTERBURN_AKAMAI_PUBLIC_IPV4_0=192.0.2.10/32",
+        ])
+    );
+    // No hostname on this platform.
+    assert!(!outputs.hostname.exists());
+
+    // JSON values are typed where declared, IDs are kept as strings
+    let output = multi_with(
+        "akamai",
+        &server,
+        &outputs,
+        &["--attributes-format", "json"],
+    );
+    assert!(output.status.success());
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&outputs.attributes).unwrap()).unwrap();
+    assert_eq!(json["AFTERBURN_AKAMAI_INSTANCE_ID"], "123");
+    assert_eq!(json["AFTERBURN_AKAMAI_INSTANCE_VCPUS"], 1);
+    assert_eq!(json["AFTERBURN_AKAMAI_INSTANCE_BACKUPS_ENABLED"], false);
+    assert_eq!(json["AFTERBURN_AKAMAI_INSTANCE_TAGS"], "a:b");
+}
+
+#[test]
+fn test_aws() {
+    let mut server = server();
+    server
+        .mock("PUT", "/latest/api/token")
+        .match_header("x-aws-ec2-metadata-token-ttl-seconds", "21600")
+        .with_body("session-token")
+        .create();
+    let base = "/2021-01-03";
+    for (key, value) in [
+        ("meta-data/hostname", "ip-10-0-0-1.ec2.internal"),
+        ("meta-data/instance-id", "i-0123456789"),
+        ("meta-data/local-ipv4", "10.0.0.1"),
+        ("meta-data/placement/availability-zone", "us-east-1a"),
+        (
+            "dynamic/instance-identity/document",
+            r#"{"region": "us-east-1"}"#,
+        ),
+    ] {
+        server
+            .mock("GET", format!("{base}/{key}").as_str())
+            .match_header("x-aws-ec2-metadata-token", "session-token")
+            .with_body(value)
+            .expect_at_least(1)
+            .create();
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("aws", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_AWS_AVAILABILITY_ZONE=us-east-1a",
+            "AFTERBURN_AWS_HOSTNAME=ip-10-0-0-1.ec2.internal",
+            "AFTERBURN_AWS_INSTANCE_ID=i-0123456789",
+            "AFTERBURN_AWS_IPV4_LOCAL=10.0.0.1",
+            "AFTERBURN_AWS_REGION=us-east-1",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "ip-10-0-0-1.ec2.internal");
+}
+
+#[test]
+fn test_azure() {
+    let mut server = server();
+    serve(
+        &mut server,
+        "/?comp=versions",
+        "<Versions><Supported><Version>2012-11-30</Version></Supported></Versions>",
+    );
+    serve(
+        &mut server,
+        "/machine/?comp=goalstate",
+        r#"<GoalState>
+  <Incarnation>1</Incarnation>
+  <Container>
+    <ContainerId>container-1</ContainerId>
+    <RoleInstanceList>
+      <RoleInstance>
+        <InstanceId>instance-1</InstanceId>
+        <Configuration>
+          <SharedConfig>http://168.63.129.16:80/machine/shared?comp=config&amp;type=sharedConfig</SharedConfig>
+        </Configuration>
+      </RoleInstance>
+    </RoleInstanceList>
+  </Container>
+</GoalState>"#,
+    );
+    serve(
+        &mut server,
+        "/machine/shared?comp=config&type=sharedConfig",
+        r#"<SharedConfig>
+  <Incarnation instance="instance-1" />
+  <Instances>
+    <Instance id="instance-1" address="10.0.0.4">
+      <InputEndpoints>
+        <Endpoint loadBalancedPublicAddress="192.0.2.4:22" />
+      </InputEndpoints>
+    </Instance>
+  </Instances>
+</SharedConfig>"#,
+    );
+    for (path, value) in [
+        (
+            "/metadata/instance/compute/name?api-version=2017-08-01&format=text",
+            "azure-vm",
+        ),
+        (
+            "/metadata/instance/compute/vmSize?api-version=2017-08-01&format=text",
+            "Standard_B1s",
+        ),
+        (
+            "/metadata/instance/compute?api-version=2017-12-01&format=json",
+            r#"{"location": "westeurope", "name": "azure-vm", "resourceGroupName": "group", "subscriptionId": "sub-1", "vmScaleSetName": "", "zone": "1"}"#,
+        ),
+    ] {
+        server
+            .mock("GET", path)
+            .match_header("Metadata", "true")
+            .with_body(value)
+            .expect_at_least(1)
+            .create();
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("azure", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_AZURE_IPV4_DYNAMIC=10.0.0.4",
+            "AFTERBURN_AZURE_IPV4_VIRTUAL=192.0.2.4",
+            "AFTERBURN_AZURE_LOCATION=westeurope",
+            "AFTERBURN_AZURE_RESOURCE_GROUP=group",
+            "AFTERBURN_AZURE_SUBSCRIPTION_ID=sub-1",
+            "AFTERBURN_AZURE_VMSIZE=Standard_B1s",
+            "AFTERBURN_AZURE_VM_NAME=azure-vm",
+            "AFTERBURN_AZURE_ZONE=1",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "azure-vm");
+}
+
+#[test]
+fn test_digitalocean() {
+    let mut server = server();
+    serve(
+        &mut server,
+        "/metadata/v1.json",
+        r#"{
+            "hostname": "droplet-1",
+            "region": "nyc3",
+            "public_keys": [],
+            "dns": {"nameservers": ["192.0.2.53"]},
+            "interfaces": {
+                "public": [{
+                    "ipv4": {"ip_address": "192.0.2.10", "netmask": "255.255.255.0", "gateway": "192.0.2.1"},
+                    "mac": "22:36:8b:9e:1c:01",
+                    "type": "public"
+                }],
+                "private": [{
+                    "ipv4": {"ip_address": "10.132.0.2", "netmask": "255.255.0.0", "gateway": "10.132.0.1"},
+                    "mac": "22:36:8b:9e:1c:02",
+                    "type": "private"
+                }]
+            }
+        }"#,
+    );
+
+    let outputs = Outputs::new();
+    let output = multi("digitalocean", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_DIGITALOCEAN_HOSTNAME=droplet-1",
+            "AFTERBURN_DIGITALOCEAN_IPV4_PRIVATE_0=10.132.0.2",
+            "AFTERBURN_DIGITALOCEAN_IPV4_PUBLIC_0=192.0.2.10",
+            "AFTERBURN_DIGITALOCEAN_REGION=nyc3",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "droplet-1");
+}
+
+#[test]
+fn test_exoscale() {
+    let mut server = server();
+    let attributes = [
+        ("instance-id", "EXOSCALE_INSTANCE_ID", "uuid-1"),
+        ("local-hostname", "EXOSCALE_LOCAL_HOSTNAME", "exo-host"),
+        ("public-hostname", "EXOSCALE_PUBLIC_HOSTNAME", "exo-host"),
+        (
+            "availability-zone",
+            "EXOSCALE_AVAILABILITY_ZONE",
+            "ch-gva-2",
+        ),
+        ("public-ipv4", "EXOSCALE_PUBLIC_IPV4", "192.0.2.10"),
+        ("local-ipv4", "EXOSCALE_LOCAL_IPV4", "10.0.0.10"),
+        ("service-offering", "EXOSCALE_SERVICE_OFFERING", "medium"),
+        (
+            "cloud-identifier",
+            "EXOSCALE_CLOUD_IDENTIFIER",
+            "CloudStack",
+        ),
+        ("vm-id", "EXOSCALE_VM_ID", "uuid-1"),
+    ];
+    for (path, _, value) in attributes {
+        serve(&mut server, &format!("/1.0/meta-data/{path}"), value);
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("exoscale", &server, &outputs);
+    assert!(output.status.success());
+    let expected: Vec<_> = attributes
+        .iter()
+        .map(|(_, key, value)| format!("AFTERBURN_{key}={value}"))
+        .collect();
+    assert_eq!(
+        outputs.attributes(),
+        lines(&expected.iter().map(String::as_str).collect::<Vec<_>>())
+    );
+    assert_eq!(outputs.hostname(), "exo-host");
+}
+
+#[test]
+fn test_gcp() {
+    let mut server = server();
+    let base = "/computeMetadata/v1/instance";
+    for (key, value) in [
+        ("hostname", "gcp-host.c.project.internal"),
+        ("network-interfaces/0/ip", "10.128.0.2"),
+        ("machine-type", "projects/1/machineTypes/e2-small"),
+    ] {
+        server
+            .mock("GET", format!("{base}/{key}").as_str())
+            .match_header("Metadata-Flavor", "Google")
+            .with_body(value)
+            .expect_at_least(1)
+            .create();
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("gcp", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_GCP_HOSTNAME=gcp-host.c.project.internal",
+            "AFTERBURN_GCP_IP_LOCAL_0=10.128.0.2",
+            "AFTERBURN_GCP_MACHINE_TYPE=projects/1/machineTypes/e2-small",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "gcp-host.c.project.internal");
+}
+
+#[test]
+fn test_gcp_kubelet_config() {
+    let mut server = server();
+    let base = "/computeMetadata/v1";
+    for (key, value) in [
+        ("instance/hostname", "node-0.c.my-project.internal"),
+        ("instance/name", "node-0"),
+        ("instance/zone", "projects/123456/zones/europe-west1-b"),
+        ("project/project-id", "my-project"),
+    ] {
+        serve(&mut server, &format!("{base}/{key}"), value);
+    }
+
+    let outputs = Outputs::new();
+    let dropin = outputs.attributes.with_file_name("10-afterburn.conf");
+    let output = multi_with(
+        "gcp",
+        &server,
+        &outputs,
+        &["--kubelet-config", dropin.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        read(&dropin),
+        concat!(
+            "[Service]\n",
+            "Environment=\"KUBELET_PROVIDER_ID=gce://my-project/europe-west1-b/node-0\"\n",
+            "Environment=\"KUBELET_NODE_LABELS=topology.kubernetes.io/region=europe-west1,topology.kubernetes.io/zone=europe-west1-b\"\n",
+        )
+    );
+}
+
+#[test]
+fn test_hetzner() {
+    let mut server = server();
+    let base = "/hetzner/v1/metadata";
+    serve(
+        &mut server,
+        base,
+        concat!(
+            "availability-zone: fsn1-dc14\n",
+            "hostname: hetzner-host\n",
+            "instance-id: 42\n",
+            "public-ipv4: 192.0.2.10\n",
+            "region: eu-central\n",
+        ),
+    );
+    serve(
+        &mut server,
+        &format!("{base}/private-networks"),
+        "- ip: 10.0.0.2\n",
+    );
+    serve(&mut server, &format!("{base}/hostname"), "hetzner-host");
+
+    let outputs = Outputs::new();
+    let output = multi("hetzner", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_HETZNER_AVAILABILITY_ZONE=fsn1-dc14",
+            "AFTERBURN_HETZNER_HOSTNAME=hetzner-host",
+            "AFTERBURN_HETZNER_INSTANCE_ID=42",
+            "AFTERBURN_HETZNER_PRIVATE_IPV4_0=10.0.0.2",
+            "AFTERBURN_HETZNER_PUBLIC_IPV4=192.0.2.10",
+            "AFTERBURN_HETZNER_REGION=eu-central",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "hetzner-host");
+}
+
+#[test]
+fn test_openstack_metadata() {
+    let mut server = server();
+    serve(
+        &mut server,
+        "/openstack/2012-08-10/meta_data.json",
+        &read(Path::new(
+            "tests/fixtures/openstack-metadata/openstack/2012-08-10/meta_data.json",
+        )),
+    );
+    for (key, value) in [
+        ("hostname", "mule"),
+        ("instance-id", "i-0000b3c1"),
+        ("instance-type", "m1.small"),
+        ("local-ipv4", "10.0.0.5"),
+    ] {
+        serve(&mut server, &format!("/latest/meta-data/{key}"), value);
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("openstack-metadata", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_OPENSTACK_AVAILABILITY_ZONE=nova",
+            "AFTERBURN_OPENSTACK_HOSTNAME=mule",
+            "AFTERBURN_OPENSTACK_INSTANCE_ID=i-0000b3c1",
+            "AFTERBURN_OPENSTACK_INSTANCE_TYPE=m1.small",
+            "AFTERBURN_OPENSTACK_INSTANCE_UUID=99dcf33b-6eb5-4acf-9abb-d81723e0c949",
+            "AFTERBURN_OPENSTACK_IPV4_LOCAL=10.0.0.5",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "mule");
+}
+
+#[test]
+fn test_packet() {
+    let mut server = server();
+    serve(
+        &mut server,
+        "/metadata",
+        r#"{
+            "id": "packet-1",
+            "hostname": "packet-host",
+            "iqn": "iqn.2024-01.net.packet:device.packet-1",
+            "plan": "c3.small.x86",
+            "facility": "am6",
+            "tags": [],
+            "ssh_keys": [],
+            "network": {
+                "interfaces": [],
+                "addresses": [{
+                    "id": "address-1",
+                    "address_family": 4,
+                    "netmask": "255.255.255.254",
+                    "public": true,
+                    "management": true,
+                    "address": "192.0.2.11",
+                    "gateway": "192.0.2.10"
+                }],
+                "bonding": {"mode": 4}
+            },
+            "phone_home_url": "https://metadata.packet.net/phone-home"
+        }"#,
+    );
+
+    let outputs = Outputs::new();
+    let output = multi("packet", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_PACKET_HOSTNAME=packet-host",
+            "AFTERBURN_PACKET_IPV4_PUBLIC_0=192.0.2.11",
+            "AFTERBURN_PACKET_IPV4_PUBLIC_GATEWAY_0=192.0.2.10",
+            "AFTERBURN_PACKET_PHONE_HOME_URL=https://metadata.packet.net/phone-home",
+            "AFTERBURN_PACKET_PLAN=c3.small.x86",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "packet-host");
+}
+
+#[test]
+fn test_scaleway() {
+    let mut server = server();
+    serve(
+        &mut server,
+        "/conf?format=json",
+        r#"{
+            "commercial_type": "DEV1-S",
+            "hostname": "scw-host",
+            "id": "scw-1",
+            "location": {"zone_id": "fr-par-1"},
+            "private_ip": "10.1.0.2",
+            "public_ip": {"address": "192.0.2.20"},
+            "ssh_public_keys": []
+        }"#,
+    );
+    let checkin = server
+        .mock("PATCH", "/state")
+        .match_body(r#"{"state_detail":"booted"}"#)
+        .create();
+
+    let outputs = Outputs::new();
+    let output = multi_with("scaleway", &server, &outputs, &["--check-in"]);
+    assert!(output.status.success());
+    checkin.assert();
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_SCALEWAY_HOSTNAME=scw-host",
+            "AFTERBURN_SCALEWAY_INSTANCE_ID=scw-1",
+            "AFTERBURN_SCALEWAY_INSTANCE_TYPE=DEV1-S",
+            "AFTERBURN_SCALEWAY_IPV4_PRIVATE=10.1.0.2",
+            "AFTERBURN_SCALEWAY_IPV4_PUBLIC=192.0.2.20",
+            "AFTERBURN_SCALEWAY_ZONE_ID=fr-par-1",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "scw-host");
+}
+
+#[test]
+fn test_vultr() {
+    let mut server = server();
+    for (key, value) in [
+        ("hostname", "vultr-host"),
+        ("instanceid", "12345"),
+        ("region/regioncode", "AMS"),
+    ] {
+        serve(&mut server, &format!("/v1/{key}"), value);
+    }
+
+    let outputs = Outputs::new();
+    let output = multi("vultr", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_VULTR_HOSTNAME=vultr-host",
+            "AFTERBURN_VULTR_INSTANCE_ID=12345",
+            "AFTERBURN_VULTR_REGION_CODE=AMS",
+        ])
+    );
+    assert_eq!(outputs.hostname(), "vultr-host");
+}
+
+#[test]
+fn test_normalized_attributes() {
+    let mut server = server();
+    for (key, value) in [
+        ("hostname", "vultr-host"),
+        ("instanceid", "12345"),
+        ("region/regioncode", "AMS"),
+    ] {
+        serve(&mut server, &format!("/v1/{key}"), value);
+    }
+
+    let outputs = Outputs::new();
+    let output = multi_with("vultr", &server, &outputs, &["--normalized-attributes"]);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "AFTERBURN_INSTANCE_ID=12345",
+            "AFTERBURN_PLATFORM=vultr",
+            "AFTERBURN_REGION=AMS",
+            "AFTERBURN_VULTR_HOSTNAME=vultr-host",
+            "AFTERBURN_VULTR_INSTANCE_ID=12345",
+            "AFTERBURN_VULTR_REGION_CODE=AMS",
+        ])
+    );
+}
+
+#[test]
+fn test_config_dir() {
+    let mut server = server();
+    for (key, value) in [
+        ("hostname", "vultr-host"),
+        ("instanceid", "12345"),
+        ("region/regioncode", "AMS"),
+    ] {
+        serve(&mut server, &format!("/v1/{key}"), value);
+    }
+
+    let outputs = Outputs::new();
+    outputs.config("[attributes]\nexclude = [\"VULTR_REGION_*\"]\nprefix = \"META_\"\n");
+    let output = multi("vultr", &server, &outputs);
+    assert!(output.status.success());
+    assert_eq!(
+        outputs.attributes(),
+        lines(&[
+            "META_VULTR_HOSTNAME=vultr-host",
+            "META_VULTR_INSTANCE_ID=12345"
+        ])
+    );
+}
+
+#[test]
+fn test_base_url_override() {
+    let mut configured = server();
+    serve(&mut configured, "/v1/hostname", "vultr-host");
+    let mut overriding = server();
+    serve(&mut overriding, "/v1/hostname", "other-host");
+
+    // From configuration.
+    let outputs = Outputs::new();
+    outputs.config(&format!(
+        "[providers.vultr]\nbase-url = \"{}\"\n",
+        configured.url()
+    ));
+    let hostname = |command: &mut Command| {
+        let output = run(command
+            .args(["multi", "--provider", "vultr", "--hostname"])
+            .arg(&outputs.hostname));
+        assert!(output.status.success());
+        outputs.hostname()
+    };
+    assert_eq!(hostname(&mut afterburn(&outputs)), "vultr-host");
+
+    // From the environment, taking precedence over configuration.
+    assert_eq!(
+        hostname(afterburn(&outputs).env("AFTERBURN_METADATA_URL", overriding.url())),
+        "other-host"
+    );
+}
+
+#[test]
+fn test_metadata_unreachable() {
+    let mut server = Server::new();
+    // Client errors are not retried.
+    server
+        .mock("GET", Matcher::Any)
+        .with_status(403)
+        .expect_at_least(1)
+        .create();
+
+    let outputs = Outputs::new();
+    let output = multi("vultr", &server, &outputs);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(!outputs.attributes.exists());
+    assert!(!outputs.hostname.exists());
+}